@@ -0,0 +1,41 @@
+//! State shared between the links of a promise chain.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A value which the links of a chain can each update, through
+/// `Promise::then_acc`.
+///
+/// Cloning an accumulator gives another handle to the same value. Each link
+/// holds the lock for as long as its function runs, so links on different
+/// chains sharing an accumulator take turns, and a link's function must not
+/// lock the accumulator itself.
+pub struct Accumulator<S> {
+    state: Arc<Mutex<S>>
+}
+
+impl<S> Accumulator<S> {
+    /// Creates an accumulator starting from `state`.
+    pub fn new(state: S) -> Accumulator<S> {
+        Accumulator { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Locks the value, for reading it or changing it outside of a chain.
+    ///
+    /// A link whose function panicked doesn't poison the value; whatever it
+    /// had written is kept.
+    pub fn lock(&self) -> MutexGuard<'_, S> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<S> Clone for Accumulator<S> {
+    fn clone(&self) -> Accumulator<S> {
+        Accumulator { state: self.state.clone() }
+    }
+}
+
+impl<S: Default> Default for Accumulator<S> {
+    fn default() -> Accumulator<S> {
+        Accumulator::new(S::default())
+    }
+}
@@ -0,0 +1,165 @@
+//! Cooperative cancellation of running promise functions.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use Promise;
+
+/// Tells a cancellable promise's function whether its result is still
+/// wanted.
+///
+/// Threads can't be stopped from the outside, so cancellation is
+/// cooperative: a long-running function should check `is_cancelled` now and
+/// then, and return early once it is set.
+#[derive(Debug, Clone)]
+pub struct CancelFlag {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>
+}
+
+impl CancelFlag {
+    pub(crate) fn new() -> CancelFlag {
+        CancelFlag {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: None
+        }
+    }
+
+    pub(crate) fn with_deadline(deadline: Instant) -> CancelFlag {
+        CancelFlag { deadline: Some(deadline), ..CancelFlag::new() }
+    }
+
+    /// The time by which the promise must settle, if it has a deadline.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Whether the promise has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Cancels a guarded promise's function when dropped.
+///
+/// Returned by `Promise::new_guarded`; keep it alive for as long as the
+/// promise's result is wanted.
+#[derive(Debug)]
+pub struct CancelGuard {
+    flag: CancelFlag
+}
+
+impl CancelGuard {
+    pub(crate) fn new(flag: CancelFlag) -> CancelGuard {
+        CancelGuard { flag }
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.flag.cancel();
+    }
+}
+
+/// A handle for cancelling a promise's function from outside it.
+///
+/// Returned by `Promise::new_with_token` and `Promise::cancel_token`.
+/// Cloning a token gives another handle to the same flag.
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    flag: CancelFlag
+}
+
+impl CancelToken {
+    pub(crate) fn new(flag: CancelFlag) -> CancelToken {
+        CancelToken { flag }
+    }
+
+    /// Tells the promise's function to stop. This doesn't wait for it to do
+    /// so.
+    pub fn cancel(&self) {
+        self.flag.cancel();
+    }
+
+    /// Whether the promise has been cancelled, through this token or
+    /// otherwise.
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.is_cancelled()
+    }
+}
+
+/// A nursery for cancellable promises, which cancels all of them when it is
+/// cancelled or dropped.
+///
+/// Promises join a scope by being spawned on it, or by being aggregated
+/// with `Promise::all_child_of`. Only promises with a `CancelFlag`, such as
+/// those from `new_cancellable`, can be told to stop; others are unaffected.
+#[derive(Debug)]
+pub struct PromiseScope {
+    // `None` once the scope is cancelled
+    children: Mutex<Option<Vec<CancelFlag>>>
+}
+
+impl PromiseScope {
+    /// Creates a scope with no children yet.
+    pub fn new() -> PromiseScope {
+        PromiseScope { children: Mutex::new(Some(Vec::new())) }
+    }
+
+    /// Creates a cancellable promise belonging to this scope, as with
+    /// `Promise::new_cancellable`.
+    pub fn spawn<T, E, F>(&self, func: F) -> Promise<T, E>
+    where T: Send + 'static, E: Send + 'static,
+    F: FnOnce(&CancelFlag) -> Result<T, E>, F: Send + 'static {
+        let promise = Promise::new_cancellable(func);
+        if let Some(ref flag) = promise.cancel {
+            self.adopt(flag.clone());
+        }
+        promise
+    }
+
+    /// Cancels every child of the scope, including those which join it
+    /// later.
+    pub fn cancel(&self) {
+        if let Some(children) = self.children().take() {
+            for child in children {
+                child.cancel();
+            }
+        }
+    }
+
+    /// Whether the scope has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.children().is_none()
+    }
+
+    /// Makes the flag a child of the scope, cancelling it straight away if
+    /// the scope already has been.
+    pub(crate) fn adopt(&self, flag: CancelFlag) {
+        match *self.children() {
+            Some(ref mut children) => children.push(flag),
+            None => flag.cancel()
+        }
+    }
+
+    fn children(&self) -> MutexGuard<'_, Option<Vec<CancelFlag>>> {
+        self.children.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for PromiseScope {
+    fn default() -> PromiseScope {
+        PromiseScope::new()
+    }
+}
+
+impl Drop for PromiseScope {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
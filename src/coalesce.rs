@@ -0,0 +1,89 @@
+//! Sharing one in-flight promise between callers asking for the same work.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use limit;
+use notify::{channel, Sender};
+use Promise;
+use Runtime;
+
+type Waiting<K, T, E> = HashMap<K, Vec<Sender<Result<T, E>>>>;
+
+/// Coalesces identical requests for work: while the work for a key is still
+/// running, asking for the same key again waits on that work instead of
+/// starting another copy. This is also known as "single-flight".
+///
+/// Every caller receives a clone of the result. Once the work finishes the
+/// key is forgotten, so a later request starts fresh work. Cloning a
+/// `Coalescer` shares its in-flight work.
+pub struct Coalescer<K, T: Send, E: Send> {
+    in_flight: Arc<Mutex<Waiting<K, T, E>>>
+}
+
+impl<K, T, E> Coalescer<K, T, E>
+where K: Hash + Eq + Clone + Send + 'static,
+T: Clone + Send + 'static, E: Clone + Send + 'static {
+    /// Creates a coalescer with no work in flight.
+    pub fn new() -> Coalescer<K, T, E> {
+        Coalescer { in_flight: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Returns a promise for the work running under `key`, or spawns `func`
+    /// as that work if there is none. Only the first caller's `func` is used.
+    ///
+    /// If `func` panics, every promise waiting on it dies.
+    pub fn get_or_spawn<F>(&self, key: K, func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let (tx, rx) = channel();
+
+        {
+            let mut in_flight = lock(&self.in_flight);
+            if let Some(waiting) = in_flight.get_mut(&key) {
+                waiting.push(tx);
+                return Promise::from_channel(rx);
+            }
+            in_flight.insert(key.clone(), vec![tx]);
+        }
+
+        let in_flight = self.in_flight.clone();
+        Runtime::global().execute(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                limit::run(func)
+            }));
+            let waiting = lock(&in_flight).remove(&key).unwrap_or_default();
+            match result {
+                Ok(result) => {
+                    for tx in waiting {
+                        tx.send(result.clone()).unwrap_or(());
+                    }
+                }
+                // The waiting senders were removed so the promises die
+                Err(payload) => panic::resume_unwind(payload)
+            }
+        }));
+
+        Promise::from_channel(rx)
+    }
+}
+
+impl<K, T: Send, E: Send> Clone for Coalescer<K, T, E> {
+    fn clone(&self) -> Coalescer<K, T, E> {
+        Coalescer { in_flight: self.in_flight.clone() }
+    }
+}
+
+impl<K, T, E> Default for Coalescer<K, T, E>
+where K: Hash + Eq + Clone + Send + 'static,
+T: Clone + Send + 'static, E: Clone + Send + 'static {
+    fn default() -> Coalescer<K, T, E> {
+        Coalescer::new()
+    }
+}
+
+fn lock<K, T, E>(in_flight: &Mutex<Waiting<K, T, E>>)
+                 -> MutexGuard<'_, Waiting<K, T, E>> {
+    in_flight.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,151 @@
+//! Aggregating promises which are submitted over time, rather than all at once.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use notify::{self, channel, Receiver, Sender};
+use error::{self, PanicSlot};
+use relay;
+use Promise;
+
+/// Collects promises submitted from anywhere, then waits for all of them
+/// like `Promise::all` once it has been closed.
+///
+/// Cloning a collector gives another handle for submitting to the same
+/// batch. The batch is finished by calling `close` on any one handle;
+/// submitting after that panics. Values are kept in submission order. If a
+/// submitted promise dies, so does the batch, as with `Promise::all`.
+pub struct AllCollector<T: Send, E: Send> {
+    shared: Arc<Shared<T, E>>
+}
+
+struct Shared<T: Send, E: Send> {
+    batch: Mutex<Batch<T, E>>,
+    result: Mutex<Option<Promise<Vec<T>, E>>>
+}
+
+struct Batch<T, E> {
+    // `None` once the collector is closed
+    events: Option<Sender<Event<T, E>>>,
+    submitted: usize
+}
+
+enum Event<T, E> {
+    Settled(usize, Result<T, E>),
+    // A promise died, with its panic slot if it had one
+    Died(Option<PanicSlot>),
+    Closed(usize)
+}
+
+impl<T: Send + 'static, E: Send + 'static> AllCollector<T, E> {
+    /// Creates a collector with nothing submitted yet.
+    pub fn new() -> AllCollector<T, E> {
+        let (events_tx, events_rx) = channel();
+        let (tx, rx) = channel();
+        let slot = PanicSlot::default();
+        let worker_slot = slot.clone();
+
+        relay(move || {
+            collect(tx, events_rx, &worker_slot);
+        });
+
+        AllCollector {
+            shared: Arc::new(Shared {
+                batch: Mutex::new(Batch { events: Some(events_tx), submitted: 0 }),
+                result: Mutex::new(Some(Promise::with_panic(rx, slot)))
+            })
+        }
+    }
+
+    /// Adds a promise to the batch.
+    ///
+    /// # Panics
+    /// Panics if the collector has already been closed.
+    pub fn submit(&self, promise: Promise<T, E>) {
+        let mut batch = self.batch();
+        let index = batch.submitted;
+        let events = match batch.events {
+            Some(ref events) => events.clone(),
+            None => panic!("Unable to submit: the AllCollector is closed")
+        };
+        batch.submitted += 1;
+
+        let panic = promise.panic.clone();
+        let recv = promise.into_channel();
+        notify::when_settled(recv, move |recv| {
+            let event = match recv.recv() {
+                Ok(result) => Event::Settled(index, result),
+                Err(_) => Event::Died(panic)
+            };
+            events.send(event).unwrap_or(());
+        });
+    }
+
+    /// Closes the batch. The result resolves once every promise submitted so
+    /// far has settled.
+    pub fn close(self) {
+        let mut batch = self.batch();
+        if let Some(events) = batch.events.take() {
+            events.send(Event::Closed(batch.submitted)).unwrap_or(());
+        }
+    }
+
+    /// Takes the promise for the batch's values, or the first error.
+    ///
+    /// # Panics
+    /// Panics if the result was already taken through any handle.
+    pub fn result(&self) -> Promise<Vec<T>, E> {
+        let mut result =
+            self.shared.result.lock().unwrap_or_else(|e| e.into_inner());
+        result.take().expect("The AllCollector's result was already taken")
+    }
+
+    fn batch(&self) -> MutexGuard<'_, Batch<T, E>> {
+        self.shared.batch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Send, E: Send> Clone for AllCollector<T, E> {
+    fn clone(&self) -> AllCollector<T, E> {
+        AllCollector { shared: self.shared.clone() }
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Default for AllCollector<T, E> {
+    fn default() -> AllCollector<T, E> {
+        AllCollector::new()
+    }
+}
+
+fn collect<T, E>(tx: Sender<Result<Vec<T>, E>>, events: Receiver<Event<T, E>>,
+                 slot: &PanicSlot) {
+    let mut values: Vec<Option<T>> = Vec::new();
+    let mut settled = 0;
+    let mut total = None;
+
+    for event in events {
+        match event {
+            Event::Settled(index, result) => {
+                settled += 1;
+                match result {
+                    Ok(val) => {
+                        if values.len() <= index {
+                            values.resize_with(index + 1, || None);
+                        }
+                        values[index] = Some(val);
+                    }
+                    Err(err) => {
+                        tx.send(Err(err)).unwrap_or(());
+                        return;
+                    }
+                }
+            }
+            Event::Died(panic) => return error::pass_panic(&panic, slot),
+            Event::Closed(submitted) => total = Some(submitted)
+        }
+
+        if total == Some(settled) {
+            tx.send(Ok(values.into_iter().flatten().collect())).unwrap_or(());
+            return;
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Error types produced by the promise machinery itself, as opposed to the
+//! `E` values produced by the user's functions.
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+
+/// Where a promise's worker records the message of a panic which killed it,
+/// so that waiting on the promise can report it. Set at most once.
+pub(crate) type PanicSlot = Arc<OnceLock<Option<String>>>;
+
+/// The message of a panic caught in a promise's worker thread.
+///
+/// Panic payloads are untyped, but nearly all of them are created by
+/// `panic!` and are either a `&'static str` or a `String`. Those two cases
+/// are extracted; anything else is reported as `"Box<dyn Any>"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicMessage {
+    /// The panic's message, if it could be read from the payload.
+    pub message: String,
+    /// The name of the thread which panicked, if it had one.
+    pub thread_name: Option<String>
+}
+
+/// How the running build handles panics, as set by the `panic` profile
+/// setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicStrategy {
+    /// Panics unwind the panicking thread, so promises can catch them.
+    Unwind,
+    /// Panics abort the whole process, so no promise can catch them.
+    Abort
+}
+
+impl PanicMessage {
+    /// Reads the message out of a payload returned by `catch_unwind` or
+    /// `JoinHandle::join`. The thread name is taken from the current thread,
+    /// so this should be called on the thread which panicked.
+    pub fn from_payload(payload: &(dyn Any + Send)) -> PanicMessage {
+        PanicMessage {
+            message: payload_message(payload)
+                .unwrap_or_else(|| "Box<dyn Any>".to_string()),
+            thread_name: thread::current().name().map(String::from)
+        }
+    }
+}
+
+/// Reads the message of a panic payload, if it is a string.
+fn payload_message(payload: &(dyn Any + Send)) -> Option<String> {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        Some(message.to_string())
+    }
+    else {
+        payload.downcast_ref::<String>().cloned()
+    }
+}
+
+/// Records the panic a promise died of, if it kept one, as the panic of a
+/// promise which dies along with it.
+pub(crate) fn pass_panic(from: &Option<PanicSlot>, to: &PanicSlot) {
+    if let Some(message) = from.as_ref().and_then(|slot| slot.get()) {
+        to.get_or_init(|| message.clone());
+    }
+}
+
+/// Runs a function, recording the message into the slot if it panics. The
+/// panic then carries on, so the worker still dies as it would have.
+pub(crate) fn record_panic<R, F: FnOnce() -> R>(slot: &PanicSlot, func: F)
+                                                 -> R {
+    match panic::catch_unwind(AssertUnwindSafe(func)) {
+        Ok(result) => result,
+        Err(payload) => {
+            slot.get_or_init(|| payload_message(&*payload));
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+impl fmt::Display for PanicMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.thread_name {
+            Some(ref name) =>
+                write!(f, "thread '{}' panicked: {}", name, self.message),
+            None => write!(f, "promise panicked: {}", self.message)
+        }
+    }
+}
+
+impl Error for PanicMessage { }
+
+/// The error of a promise which did not settle before its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "promise did not settle before its deadline")
+    }
+}
+
+impl Error for DeadlineExceeded { }
+
+/// The error of a promise which was cancelled through its `CancelToken`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "promise was cancelled")
+    }
+}
+
+impl Error for Cancelled { }
+
+/// Why a batch of promises failed, with the index of the promise at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllError<E> {
+    /// The promise at this index rejected with the error.
+    Rejected(usize, E),
+    /// The function of the promise at this index panicked.
+    Panicked(usize)
+}
+
+impl<E: fmt::Display> fmt::Display for AllError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AllError::Rejected(index, ref err) =>
+                write!(f, "promise {} rejected: {}", index, err),
+            AllError::Panicked(index) =>
+                write!(f, "promise {} panicked", index)
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for AllError<E> { }
+
+/// The error of a promise which can be stopped early by a signal, as with
+/// `Promise::until_signal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerminatedOr<E> {
+    /// The signal fired before the promise settled.
+    Terminated,
+    /// The promise rejected with the error.
+    Rejected(E)
+}
+
+impl<E: fmt::Display> fmt::Display for TerminatedOr<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TerminatedOr::Terminated =>
+                write!(f, "promise was terminated by a signal"),
+            TerminatedOr::Rejected(ref err) => err.fmt(f)
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for TerminatedOr<E> { }
+
+/// Why waiting on a promise didn't produce a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromiseError<E> {
+    /// The promise rejected with the error.
+    Rejected(E),
+    /// The promise's function panicked, with the panic's message if it was a
+    /// string.
+    Panicked(Option<String>),
+    /// The promise's function died without settling, other than by a panic
+    /// the promise could see, such as a work item which was never run.
+    Dead
+}
+
+impl<E: fmt::Display> fmt::Display for PromiseError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PromiseError::Rejected(ref err) => err.fmt(f),
+            PromiseError::Panicked(Some(ref message)) =>
+                write!(f, "promise panicked: {}", message),
+            PromiseError::Panicked(None) => write!(f, "promise panicked"),
+            PromiseError::Dead =>
+                write!(f, "promise died without settling")
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for PromiseError<E> { }
+
+/// Why `Promise::wait_timeout` didn't produce the promise's result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeoutError {
+    /// The promise didn't settle within the timeout.
+    TimedOut,
+    /// The promise's function panicked, as with `PromiseError::Panicked`.
+    Panicked(Option<String>),
+    /// The promise's function died without settling, as with
+    /// `PromiseError::Dead`.
+    Dead
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeoutError::TimedOut =>
+                write!(f, "promise did not settle in time"),
+            TimeoutError::Panicked(Some(ref message)) =>
+                write!(f, "promise panicked: {}", message),
+            TimeoutError::Panicked(None) => write!(f, "promise panicked"),
+            TimeoutError::Dead => write!(f, "promise died without settling")
+        }
+    }
+}
+
+impl Error for TimeoutError { }
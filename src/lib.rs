@@ -3,14 +3,79 @@
 
 #![warn(missing_docs)]
 
+#[macro_use]
+mod macros;
 #[cfg(test)]
 mod tests;
+mod accumulator;
+mod cancel;
+mod coalesce;
+mod collect;
+mod error;
+mod limit;
+mod notify;
+mod profile;
+mod race;
+mod ready;
+mod resolver;
+mod runtime;
+mod shared;
+mod spill;
+mod state;
+mod stream;
+
+pub use accumulator::Accumulator;
+pub use cancel::{CancelFlag, CancelGuard, CancelToken, PromiseScope};
+pub use coalesce::Coalescer;
+pub use collect::AllCollector;
+pub use error::{AllError, Cancelled, DeadlineExceeded, PanicMessage,
+                PanicStrategy, PromiseError, TerminatedOr, TimeoutError};
+pub use profile::{AllReport, LatencyStats, Outcome, SpanRecord, Tally,
+                  TaskTiming};
+pub use race::{RaceBuilder, RaceGroup, RaceOutcome};
+pub use ready::ReadyPromise;
+pub use resolver::Resolver;
+pub use runtime::{Cooperator, Executor, LocalPool, Runtime, WorkerId};
+pub use shared::SharedPromise;
+pub use spill::{Spill, SpillIter, SpillResult};
+pub use state::PromiseState;
+pub use stream::{PromiseStream, StreamEvent};
 
 use std::thread;
-use std::sync::mpsc::channel;
-//use std::thread::JoinHandle;
+use std::panic::{self, AssertUnwindSafe};
+use std::cell::{RefCell, RefMut};
+#[cfg(test)]
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use std::marker::{Send};
-use std::sync::mpsc::{Sender, Receiver, TryRecvError};
+use std::mem;
+use std::io;
+#[cfg(all(unix, feature = "readiness-fd"))]
+use std::io::Write;
+#[cfg(all(unix, feature = "readiness-fd"))]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+
+use error::PanicSlot;
+use notify::{channel, Sender, Receiver};
+use profile::TimingSlot;
+
+/// The work of a promise from `Promise::deferred_work`, to be run on an
+/// executor of the caller's choosing.
+pub type WorkItem = Box<dyn FnOnce() + Send>;
 
 /// A promise is a way of doing work in the background. The promises in
 /// this library have the same featureset as those in Ecmascript 5.
@@ -29,23 +94,44 @@ use std::sync::mpsc::{Sender, Receiver, TryRecvError};
 /// # Panics
 /// If the function being executed by a promise panics, it does so silently.
 /// The panic will not resurface in the thread which created the promise,
-/// and promises waiting on its result will never be called. In addition,
-/// the `all` and `race` proimse methods will _ignore_ "dead" promises. They
+/// and promises waiting on its result will never be called. `wait` is the
+/// exception: it reports the panic's message as `PromiseError::Panicked`
+/// for promises from `new`, and those chained on them with the `then`
+/// family of methods, which share a record of the panic. The `all` family
+/// of methods die along with any of their promises, passing its panic on.
+/// The `race` proimse methods will _ignore_ "dead" promises instead. They
 /// will remove promises from their lists, and if there aren't any left
 /// they will silently exit without doing anything.
 ///
 /// Unfortunately, panics must be ignored for two reasons:
 /// * Panic messages don't have a concrete type yet in Rust. If they did,
-/// promiess would be able to inspect their predecessors' errors.
+///   promiess would be able to inspect their predecessors' errors.
 /// * Although a `Receiver` can correctly handle its paired `Sender` being
-/// dropped, such as during a panic, for reasons stated above the "message"
-/// of the panic is not relayed.
+///   dropped, such as during a panic, for reasons stated above the "message"
+///   of the panic is not relayed.
+///
+/// Promises created with `new_catch` work around this by catching the panic
+/// in the worker and rejecting with its `PanicMessage`. This relies on the
+/// panic unwinding: under `panic = "abort"`, any panic in a promise's
+/// function aborts the whole process, and `Promise::panic_strategy` reports
+/// which kind of build is running.
 ///
 /// Finally, Ecmascript promises themselves do have the ability to return
 /// and error type, represented as a `Result<T, E>` here. Thus, one should
 /// use `try!` and other error handling rather than calls to `unwrap()`.
 pub struct Promise<T: Send, E: Send> {
-    receiver: Receiver<Result<T, E>>
+    inner: RefCell<Inner<T, E>>,
+    handle: Option<JoinHandle<()>>,
+    cancel: Option<CancelFlag>,
+    timing: Option<TimingSlot>,
+    // Where the worker records a panic, for promises which can see one
+    panic: Option<PanicSlot>,
+    // The runtime the promise's function was queued on, if it was
+    runtime: Option<runtime::Owner>,
+    // Starts the work of a `lazy` promise which hasn't been demanded yet
+    start: RefCell<Option<WorkItem>>,
+    #[cfg(all(unix, feature = "readiness-fd"))]
+    readiness: RefCell<Option<io::PipeReader>>
 }
 
 impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
@@ -58,14 +144,84 @@ impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
     where T2: Send + 'static, E2: Send + 'static,
     F1: FnOnce(T) -> Result<T2, E2>, F2: FnOnce(E) -> Result<T2, E2>,
     F1: Send + 'static, F2: Send + 'static {
-        let recv = self.receiver;
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_then(tx, recv, callback, errback);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_then(tx, recv, callback, errback);
+            });
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Like `then`, but the callbacks run on a thread with the given name, so
+    /// it can be told apart in debuggers and panic messages.
+    ///
+    /// The returned promise owns its worker thread, whose handle can be
+    /// taken with `take_handle`.
+    pub fn then_named<T2, E2, F1, F2>(self, name: &str, callback: F1,
+                                      errback: F2) -> Promise<T2, E2>
+    where T2: Send + 'static, E2: Send + 'static,
+    F1: FnOnce(T) -> Result<T2, E2>, F2: FnOnce(E) -> Result<T2, E2>,
+    F1: Send + 'static, F2: Send + 'static {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
+        let (tx, rx) = channel();
+
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                watched(&worker_slot, tx, |tx| {
+                    Promise::impl_then(tx, recv, callback, errback);
+                });
+            })
+            .expect("failed to spawn thread");
+
+        let mut promise = Promise::with_handle(rx, handle);
+        promise.panic = Some(slot);
+        promise
+    }
+
+    /// Like `then`, but the callbacks are run by `executor`, such as a small
+    /// pool for I/O or a larger one for computation.
+    ///
+    /// The callbacks are only handed to the executor once this promise has
+    /// settled, so they never hold up one of its workers while waiting, and
+    /// no thread waits in the meantime. If this promise dies, nothing is run.
+    pub fn then_on<T2, E2, F1, F2, X>(self, executor: &X, callback: F1,
+                                      errback: F2) -> Promise<T2, E2>
+    where T2: Send + 'static, E2: Send + 'static,
+    F1: FnOnce(T) -> Result<T2, E2>, F2: FnOnce(E) -> Result<T2, E2>,
+    F1: Send + 'static, F2: Send + 'static,
+    X: Executor + Clone + Send + 'static {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
+        let executor = executor.clone();
+        let (tx, rx) = channel();
+
+        notify::when_settled(recv, move |recv| {
+            let message = match recv.recv() {
+                Ok(message) => message,
+                Err(_) => return
+            };
+            executor.spawn(Box::new(move || {
+                watched(&worker_slot, tx, |tx| {
+                    let result = match message {
+                        Ok(val) => limit::run(|| callback(val)),
+                        Err(err) => limit::run(|| errback(err))
+                    };
+                    tx.send(result).unwrap_or(());
+                });
+            }));
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
     }
 
     /// Chains a function to be called after this promise resolves,
@@ -73,14 +229,18 @@ impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
     pub fn then_result<T2, E2, F>(self, resultback: F) -> Promise<T2, E2>
     where T2: Send + 'static, E2: Send + 'static,
     F: FnOnce(Result<T, E>) -> Result<T2, E2>, F: Send + 'static {
-        let recv = self.receiver;
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_then_result(tx, recv, resultback);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_then_result(tx, recv, resultback);
+            });
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
     }
 
     /// Chains functions to be called after this promise resolves,
@@ -90,15 +250,12 @@ impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
     where T2: Send + 'static, E2: Send + 'static,
     F1: FnOnce(T) -> Promise<T2, E2> + Send + 'static,
     F2: FnOnce(E) -> Promise<T2, E2> + Send + 'static {
-        let val = self.receiver.recv();
+        let val = self.take_result();
         match val {
-            Ok(result) => {
-                let promise = match result {
-                    Ok(val) => callback(val),
-                    Err(err) => errback(err)
-                };
-                return promise;
-            }
+            Ok(result) => match result {
+                Ok(val) => callback(val),
+                Err(err) => errback(err)
+            },
             Err(err) =>
                 panic!("Unable to fulfill then_promise: \
                         the previous promise panicked: {:?}", err)
@@ -111,7 +268,7 @@ impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
                                           -> Promise<T2, E2>
     where T2: Send + 'static, E2: Send + 'static,
     F: FnOnce(Result<T, E>) -> Promise<T2, E2> + Send + 'static {
-        let val = self.receiver.recv();
+        let val = self.take_result();
         match val {
             Ok(result) => promiseback(result),
             Err(err) =>
@@ -123,207 +280,2928 @@ impl<T: Send + 'static, E: Send + 'static> Promise<T, E> {
     /// Calls a function on the result of the promise if it is fulfilled.
     ///
     /// This is equivalent to a Javascript promise's `then` with one
-    /// callback, or Rust's `Result::map`.
+    /// callback, or Rust's `Result::map`. An error is passed on as it is,
+    /// without being copied.
     pub fn then_ok<T2, F>(self, callback: F) -> Promise<T2, E>
     where T2: Send + 'static, F: Send + 'static,
     F: FnOnce(T) -> Result<T2, E> {
-        let recv = self.receiver;
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_ok_then(tx, recv, callback);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_ok_then(tx, recv, callback);
+            });
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Calls a function on the value of the promise if it is fulfilled, like
+    /// `then_ok`, giving it mutable access to the accumulator's state.
+    ///
+    /// The accumulator is locked while the function runs.
+    pub fn then_acc<T2, S, F>(self, acc: &Accumulator<S>, callback: F)
+                              -> Promise<T2, E>
+    where T2: Send + 'static, S: Send + 'static, F: Send + 'static,
+    F: FnOnce(T, &mut S) -> Result<T2, E> {
+        let acc = acc.clone();
+        self.then_ok(move |val| callback(val, &mut *acc.lock()))
+    }
+
+    /// Transforms the promise's value with a function which can't fail,
+    /// passing any error on untouched. This is like `Result::map`.
+    pub fn map<T2, F>(self, func: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: FnOnce(T) -> T2, F: Send + 'static {
+        self.then_ok(move |val| Ok(func(val)))
+    }
+
+    /// Transforms the promise's error with a function which can't fail,
+    /// passing any value on untouched. This is like `Result::map_err`, and
+    /// is handy for converting errors from another subsystem.
+    pub fn map_err<E2, F>(self, func: F) -> Promise<T, E2>
+    where E2: Send + 'static, F: FnOnce(E) -> E2, F: Send + 'static {
+        self.then_err(move |err| Err(func(err)))
+    }
+
+    /// Resolves to the promise's value, or to `default` if it rejects. This
+    /// is like `Result::unwrap_or`, and the returned promise can't reject.
+    pub fn unwrap_or(self, default: T) -> Promise<T, Infallible> {
+        self.then_err(move |_| Ok(default))
+    }
+
+    /// Resolves to the promise's value, or to `func` of the error if it
+    /// rejects, like `Result::unwrap_or_else`.
+    pub fn unwrap_or_else<F>(self, func: F) -> Promise<T, Infallible>
+    where F: FnOnce(E) -> T, F: Send + 'static {
+        self.then_err(move |err| Ok(func(err)))
+    }
+
+    /// Calls a function with a reference to the promise's value if it is
+    /// fulfilled, then passes the value on unchanged. This is like
+    /// `Result::inspect`, and suits logging and metrics.
+    pub fn inspect<F>(self, func: F) -> Promise<T, E>
+    where F: FnOnce(&T), F: Send + 'static {
+        self.then_ok(move |val| {
+            func(&val);
+            Ok(val)
+        })
+    }
+
+    /// Calls a function with a reference to the promise's error if it is
+    /// rejected, then passes the error on unchanged, like
+    /// `Result::inspect_err`.
+    pub fn inspect_err<F>(self, func: F) -> Promise<T, E>
+    where F: FnOnce(&E), F: Send + 'static {
+        self.then_err(move |err| {
+            func(&err);
+            Err(err)
+        })
+    }
+
+    /// Transforms either the value or the error of the promise into a value,
+    /// with a function for each which can't fail. The returned promise never
+    /// rejects.
+    ///
+    /// This collapses a result into one kind of value, such as a status
+    /// message to display.
+    pub fn then_map<T2, F1, F2>(self, on_ok: F1, on_err: F2) -> Promise<T2, E>
+    where T2: Send + 'static, F1: Send + 'static, F2: Send + 'static,
+    F1: FnOnce(T) -> T2, F2: FnOnce(E) -> T2 {
+        self.then(move |val| Ok(on_ok(val)), move |err| Ok(on_err(err)))
+    }
+
+    /// Transforms the promise's value with a function which can fail, so
+    /// that `?` can be used inside it. This is the same as `then_ok`.
+    ///
+    /// ```
+    /// use promises::Promise;
+    /// use std::num::ParseIntError;
+    ///
+    /// let input: Promise<&str, ParseIntError> =
+    ///     Promise::resolve("6 7").into_promise();
+    /// let product = input.try_map(|text| {
+    ///     let mut product = 1;
+    ///     for word in text.split(' ') {
+    ///         product *= word.parse::<u32>()?;
+    ///     }
+    ///     Ok(product)
+    /// });
+    /// ```
+    pub fn try_map<T2, F>(self, func: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static,
+    F: FnOnce(T) -> Result<T2, E> {
+        self.then_ok(func)
+    }
+
+    /// Settles with this promise's result, unless `signal` resolves first,
+    /// in which case it rejects with `Terminated`.
+    ///
+    /// A signal which rejects or dies never fires, and if the promise dies
+    /// the returned one dies of the same panic. This only stops waiting
+    /// on the promise; its function keeps running unless it is told to stop
+    /// some other way, such as a `CancelFlag`.
+    pub fn until_signal<S>(self, signal: Promise<(), S>)
+                           -> Promise<T, TerminatedOr<E>>
+    where S: Send + 'static {
+        let slot = self.panic_slot();
+        let recv = self.into_channel();
+        let signal = signal.into_channel();
+        let (tx, rx) = channel();
+        Promise::impl_until_signal(tx, recv, signal);
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Calls a function on the value of the promise if it is fulfilled, like
+    /// `then_ok`, calling it again on the same value each time it errors.
+    ///
+    /// The function is called at most `attempts` times, and at least once.
+    /// It borrows the value, so the value doesn't need to be `Clone`. If
+    /// every attempt fails, the last error rejects the returned promise.
+    pub fn map_retry<T2, F>(self, attempts: usize, func: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static,
+    F: Fn(&T) -> Result<T2, E> {
+        self.then_ok(move |val| {
+            let mut attempt = 1;
+            loop {
+                match func(&val) {
+                    Err(_) if attempt < attempts => attempt += 1,
+                    result => return result
+                }
+            }
+        })
+    }
+
+    /// Calls a function on the value of the promise if it is fulfilled,
+    /// which either changes the value or passes it through untouched.
+    ///
+    /// The function returns `Ok` with a new value or `Err` with the original
+    /// one; either way the returned promise resolves to it.
+    pub fn map_or_pass<F>(self, func: F) -> Promise<T, E>
+    where F: FnOnce(T) -> Result<T, T>, F: Send + 'static {
+        self.then_ok(move |val| match func(val) {
+            Ok(changed) => Ok(changed),
+            Err(original) => Ok(original)
+        })
+    }
+
+    /// Chains a clone of `func` onto each of the promises, as with `then_ok`.
+    pub fn map_each<U, F>(promises: Vec<Promise<T, E>>, func: F)
+                          -> Vec<Promise<U, E>>
+    where U: Send + 'static, F: Send + 'static,
+    F: Fn(T) -> Result<U, E> + Clone {
+        promises.into_iter()
+            .map(|promise| promise.then_ok(func.clone()))
+            .collect()
     }
 
     /// Calls a function of the result of the promise if it fails.
     ///
-    /// This is equivalent to Javascript promise's `catch`.
+    /// This is equivalent to Javascript promise's `catch`. A value is passed
+    /// on as it is, without being copied.
     pub fn then_err<E2, F>(self, errback: F) -> Promise<T, E2>
     where F: FnOnce(E) -> Result<T, E2>, F: Send + 'static,
     E2: Send + 'static {
-        let recv = self.receiver;
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_err_then(tx, recv, errback);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_err_then(tx, recv, errback);
+            });
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
     }
 
-    /// Creates a new promsie, which will eventually resolve to one of the
-    /// values of the `Result<T, E>` type.
-    pub fn new<F>(func: F) -> Promise<T, E>
-    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+    /// Calls a function on the result of the promise if it is fulfilled, like
+    /// `then_ok`, and records how long it took under `name`.
+    ///
+    /// The records of every span in the process can be drained with
+    /// `Promise::take_spans`.
+    pub fn span<T2, F>(self, name: &'static str, callback: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static,
+    F: FnOnce(T) -> Result<T2, E> {
+        self.then_ok(move |val| {
+            let start = Instant::now();
+            let result = callback(val);
+            profile::record(name, start.elapsed());
+            result
+        })
+    }
+
+    /// Calls a function on the result of the promise if it is fulfilled, like
+    /// `then_ok`, first calling `warn` if the promise took longer than
+    /// `threshold` to settle.
+    ///
+    /// The time is measured from this call, and `warn` is given how long the
+    /// promise actually took. It is not called when the promise rejects.
+    pub fn warn_if_slow<T2, F, W>(self, threshold: Duration, warn: W,
+                                  callback: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static, W: Send + 'static,
+    F: FnOnce(T) -> Result<T2, E>, W: FnOnce(Duration) {
+        let start = Instant::now();
+        self.then_ok(move |val| {
+            let waited = start.elapsed();
+            if waited > threshold {
+                warn(waited);
+            }
+            callback(val)
+        })
+    }
+
+    /// Calls a function on the result of the promise if it is fulfilled,
+    /// producing a batch of new promises, and waits for all of them like
+    /// `all`.
+    ///
+    /// An error from this promise or from any promise in the batch rejects
+    /// the returned promise.
+    pub fn fan_out<U, F>(self, callback: F) -> Promise<Vec<U>, E>
+    where U: Send + 'static, F: Send + 'static,
+    F: FnOnce(T) -> Vec<Promise<U, E>> {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_new(tx, func);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_fan_out(tx, recv, callback, worker_slot.clone());
+            });
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
     }
 
-    /// Applies a promise to the first of some promises to become fulfilled.
-    pub fn race(promises: Vec<Promise<T, E>>) -> Promise<T, E> {
-        let recs = promises.into_iter().map(|p| p.receiver).collect();
-        let (tx, rx) = channel::<Result<T, E>>();
+    /// Calls a function on the value of the promise if it is fulfilled,
+    /// which starts another promise, and settles with that promise's result.
+    ///
+    /// This flattens what would otherwise be a `Promise<Promise<T2, E>, E>`,
+    /// without blocking the calling thread like `then_promise`. Errors from
+    /// this promise are passed on without calling the function.
+    pub fn and_then<T2, F>(self, callback: F) -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static,
+    F: FnOnce(T) -> Promise<T2, E> {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
+        let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_race(tx, recs);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_and_then(tx, recv, callback, &worker_slot);
+            });
         });
 
-        Promise { receiver: rx }
+        Promise::with_panic(rx, slot)
     }
 
-    /// Calls a function with the result of all of the promises, or the error
-    /// of the first promise to error.
-    pub fn all(promises: Vec<Promise<T, E>>) -> Promise<Vec<T>, E> {
-        let receivers: Vec<Receiver<Result<T, E>>> =
-            promises.into_iter().map(|p| p.receiver).collect();
+    /// Calls a function on the error of the promise if it rejects, which can
+    /// recover to a value or reject again. A value is passed on untouched.
+    ///
+    /// This is the Javascript promise's `catch`: `then_err` for a function
+    /// keeping the same value and error types.
+    pub fn catch<F>(self, errback: F) -> Promise<T, E>
+    where F: FnOnce(E) -> Result<T, E>, F: Send + 'static {
+        self.then_err(errback)
+    }
+
+    /// If the promise rejects with an error matching `pred`, resolves to
+    /// `recover`'s value for it instead. Other errors are passed on.
+    pub fn recover_if<F, G>(self, pred: F, recover: G) -> Promise<T, E>
+    where F: FnOnce(&E) -> bool, G: FnOnce(E) -> T,
+    F: Send + 'static, G: Send + 'static {
+        self.then_err(move |err| {
+            if pred(&err) { Ok(recover(err)) } else { Err(err) }
+        })
+    }
+
+    /// If the promise rejects, starts a separate recovery pipeline from the
+    /// error and settles with its result instead.
+    ///
+    /// Unlike `then_err`, `recovery` returns a promise, so the fallback can be
+    /// a whole chain of its own, such as reading from a cache when a more
+    /// involved pipeline fails. It doesn't run if the promise resolves.
+    pub fn recover_pipeline<F>(self, recovery: F) -> Promise<T, E>
+    where F: FnOnce(E) -> Promise<T, E>, F: Send + 'static {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
 
-        thread::spawn(move || {
-            Promise::impl_all(tx, receivers);
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_recover_pipeline(tx, recv, recovery,
+                                               &worker_slot);
+            });
         });
 
-        return Promise { receiver: rx };
+        Promise::with_panic(rx, slot)
     }
 
-    /// Creates a promise that resolves to a value
-    pub fn resolve(val: T) -> Promise<T, E> {
-        Promise::from_result(Ok(val))
+    /// Calls a function once the promise settles, whether it resolved or
+    /// rejected, then passes its result on unchanged.
+    ///
+    /// The function also runs if the promise's worker died, just before the
+    /// returned promise dies too, so cleanup isn't skipped on a panic.
+    pub fn finally<F>(self, func: F) -> Promise<T, E>
+    where F: FnOnce(), F: Send + 'static {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
+        let (tx, rx) = channel();
+
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_finally(tx, recv, func);
+            });
+        });
+
+        Promise::with_panic(rx, slot)
     }
 
-    /// Creates a promise that resolves to an error.
-    pub fn reject(val: E) -> Promise<T, E> {
-        Promise::from_result(Err(val))
+    /// Passes the promise's result on if it settles within `duration`, or
+    /// else rejects with `on_timeout`.
+    ///
+    /// A timeout doesn't stop the promise's function, which keeps running in
+    /// the background and has its eventual result discarded. If the promise
+    /// is cancellable, as from `new_cancellable`, its `CancelFlag` is
+    /// cancelled so the function can stop early.
+    pub fn timeout(mut self, duration: Duration, on_timeout: E)
+                   -> Promise<T, E> {
+        let flag = self.cancel.take();
+        let slot = self.panic_slot();
+        let recv = self.into_channel();
+        let (tx, rx) = channel();
+
+        watchdog(move || {
+            Promise::impl_timeout(tx, recv, flag, duration, on_timeout);
+        });
+
+        Promise::with_panic(rx, slot)
     }
 
-    /// Creates a new promise that will resolve to the result value.
-    pub fn from_result(result: Result<T, E>) -> Promise<T, E> {
+    /// Calls `body` on the result of the promise if it is fulfilled, then
+    /// calls `finalize` exactly once whatever happened.
+    ///
+    /// `finalize` runs after `body` returns, after `body` panics, and when
+    /// `body` is skipped because this promise was rejected or died. It is
+    /// always run before the returned promise settles. This makes it a good
+    /// place to release something acquired by the worker, like a lock.
+    ///
+    /// In a build with `panic = "abort"`, a panic in `body` aborts the
+    /// process before `finalize` can run.
+    pub fn with_finalizer<T2, F, G>(self, body: F, finalize: G)
+                                    -> Promise<T2, E>
+    where T2: Send + 'static, F: Send + 'static, G: Send + 'static,
+    F: FnOnce(T) -> Result<T2, E>, G: FnOnce() {
+        let slot = self.panic_slot();
+        let worker_slot = slot.clone();
+        let recv = self.into_channel();
         let (tx, rx) = channel();
-        tx.send(result).unwrap();
 
-        Promise { receiver: rx }
+        Runtime::global().execute_after(recv, move |recv| {
+            watched(&worker_slot, tx, |tx| {
+                Promise::impl_with_finalizer(tx, recv, body, finalize);
+            });
+        });
+
+        Promise::with_panic(rx, slot)
     }
 
-    // Implementation Functions
+    /// Creates a new promsie, which will eventually resolve to one of the
+    /// values of the `Result<T, E>` type.
+    ///
+    /// The function is queued on `Runtime::global`, whose workers are shared
+    /// by every promise, so creating thousands of promises doesn't start
+    /// thousands of threads. While the function waits on another promise the
+    /// runtime lends out a spare worker, but other blocking, such as a sleep
+    /// or a synchronous network call, holds up a worker for everything
+    /// queued behind it. Such a function should use `new_blocking`.
+    pub fn new<F>(func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        Promise::new_on(&Runtime::global(), func)
+    }
 
-    fn impl_new<F>(tx: Sender<Result<T, E>>, func: F)
+    /// Creates a new promise whose function always gets a fresh thread of its
+    /// own, for work which blocks, like a synchronous database call or a
+    /// `sleep`.
+    ///
+    /// A blocked function on a shared pool holds up every task queued behind
+    /// it. On a thread of its own it can block freely. A function which only
+    /// computes, or only waits on other promises, doesn't need the thread,
+    /// and is cheaper queued on the pool with `new`.
+    ///
+    /// The promise owns its thread, whose handle can be taken with
+    /// `take_handle`.
+    pub fn new_blocking<F>(func: F) -> Promise<T, E>
     where F: FnOnce() -> Result<T, E>, F: Send + 'static {
-        let result = func();
-        tx.send(result).unwrap_or(());
+        let (tx, rx) = channel();
+        let slot = PanicSlot::default();
+        let worker_slot = slot.clone();
+
+        let handle = spawn(move || {
+            watched(&worker_slot, tx, |tx| Promise::impl_new(tx, func));
+        });
+
+        let mut promise = Promise::with_handle(rx, handle);
+        promise.panic = Some(slot);
+        promise
     }
 
-    fn impl_then<T2, E2, F1, F2>(tx: Sender<Result<T2, E2>>,
-                                 rx: Receiver<Result<T, E>>,
-                                 callback: F1, errback: F2)
-    where T2: Send + 'static, E2: Send + 'static,
-    F1: FnOnce(T) -> Result<T2, E2>, F2: FnOnce(E) -> Result<T2, E2>,
-    F1: Send + 'static, F2: Send + 'static
-    {
-        if let Ok(message) = rx.recv() {
-            match message {
-                Ok(val) => tx.send(callback(val)).unwrap_or(()),
-                Err(err) => tx.send(errback(err)).unwrap_or(())
-            };
-        }
+    /// Creates a new promise like `new`, but doesn't start its function until
+    /// its result is first needed: when it is chained, waited on or polled.
+    ///
+    /// A lazy promise which is dropped without being needed never runs. This
+    /// saves work in graphs of promises where not every branch is used.
+    /// Checking `state` doesn't count as needing the result.
+    pub fn lazy<F>(func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let (mut promise, task) = Promise::queued(func);
+        let start: WorkItem =
+            Box::new(move || Runtime::global().execute(task));
+        *promise.start.get_mut() = Some(start);
+        promise
     }
 
-    fn impl_then_result<T2, E2, F>(tx: Sender<Result<T2, E2>>,
-                                   rx: Receiver<Result<T, E>>,
-                                   resultback: F)
-    where T2: Send + 'static, E2: Send + 'static,
-    F: FnOnce(Result<T, E>) -> Result<T2, E2>, F: Send + 'static {
+    /// Creates a new promise like `new`, then calls `map` on its value on the
+    /// same worker thread, like `new(func).then_ok(map)` without queueing a
+    /// second task.
+    pub fn new_then<T0, F, G>(func: F, map: G) -> Promise<T, E>
+    where T0: Send + 'static, F: Send + 'static, G: Send + 'static,
+    F: FnOnce() -> Result<T0, E>, G: FnOnce(T0) -> Result<T, E> {
+        Promise::new(move || func().and_then(map))
+    }
 
-        if let Ok(result) = rx.recv() {
-            tx.send(resultback(result)).unwrap_or(());
-        }
+    /// Creates a new promise along with the work which settles it, leaving it
+    /// to the caller to decide where and when the work runs.
+    ///
+    /// Running the `WorkItem` calls `func` on the current thread and settles
+    /// the promise with its result. If the work item is dropped without being
+    /// run, the promise dies.
+    pub fn deferred_work<F>(func: F) -> (Promise<T, E>, WorkItem)
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let (tx, rx) = channel();
+        let work: WorkItem = Box::new(move || Promise::impl_new(tx, func));
+        (Promise::from_channel(rx), work)
     }
 
-    fn impl_ok_then<T2, F>(tx: Sender<Result<T2, E>>,
-                           rx: Receiver<Result<T, E>>, callback: F)
-    where F: FnOnce(T) -> Result<T2, E>, F: Send + 'static,
-    T2: Send + 'static {
-        if let Ok(message) = rx.recv() {
-            match message {
-                Ok(val) => tx.send(callback(val)).unwrap_or(()),
-                Err(err) => tx.send(Err(err)).unwrap_or(())
-            }
-        }
+    /// Creates a new promise like `new`, converting the error returned by
+    /// `func` into the promise's error type.
+    ///
+    /// This lets the function use `?` on errors such as `io::Error` while the
+    /// promise rejects with a domain-specific error.
+    pub fn new_try<F, E2>(func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E2>, F: Send + 'static, E2: Into<E> {
+        Promise::new(move || func().map_err(Into::into))
     }
 
-    fn impl_err_then<E2, F>(tx: Sender<Result<T, E2>>,
-                           rx: Receiver<Result<T, E>>, errback: F)
-    where F: FnOnce(E) -> Result<T, E2>, F: Send + 'static,
-    E2: Send + 'static {
-        if let Ok(message) = rx.recv() {
-            match message {
-                Ok(val) => tx.send(Ok(val)).unwrap_or(()),
-                Err(err) => tx.send(errback(err)).unwrap_or(())
+    /// Creates a new promise like `new`, but if `func` panics the panic is
+    /// caught and the promise is rejected with its `PanicMessage` instead of
+    /// silently dying.
+    ///
+    /// The error type only needs to be convertible from `PanicMessage`, so
+    /// `PanicMessage` itself or a wrapping error enum can be used.
+    ///
+    /// Panics can only be caught when they unwind. In a build with
+    /// `panic = "abort"` a panic in `func` aborts the process as usual; see
+    /// `Promise::panic_strategy`.
+    pub fn new_catch<F>(func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static,
+    E: From<PanicMessage> {
+        // The function's own panics are caught, but one converting the
+        // message into `E` still kills the promise, and is recorded
+        Promise::new(move || Promise::impl_new_catch(func))
+    }
+
+    /// Creates a promise which is settled through the returned `Resolver`,
+    /// rather than by a function of its own. This is the promise's
+    /// `(resolve, reject)` pair, for code driven by events or callbacks.
+    pub fn channel() -> (Resolver<T, E>, Promise<T, E>) {
+        let (tx, rx) = channel();
+        (Resolver::new(tx), Promise::from_channel(rx))
+    }
+
+    /// Creates a new promise whose function is queued on a `Runtime`, rather
+    /// than on the global one like `new`.
+    ///
+    /// The runtime's workers are shared by everything queued on them, so this
+    /// suits many short functions. A function which blocks, especially on
+    /// another promise queued on the same runtime, holds up a worker and can
+    /// deadlock a small runtime. Such functions should use `new_blocking`.
+    pub fn new_on<F>(runtime: &Runtime, func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let (mut promise, task) = Promise::queued(func);
+        promise.runtime = Some(runtime.owner());
+        runtime.execute(task);
+        promise
+    }
+
+    /// Creates a new promise whose function is queued on `Runtime::global`:
+    /// the installed runtime, or a default one with a worker for each CPU.
+    ///
+    /// This is what `new` does, spelled out for code which also queues on
+    /// runtimes of its own. The same caution about blocking functions
+    /// applies.
+    pub fn new_pooled<F>(func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        Promise::new_on(&Runtime::global(), func)
+    }
+
+    /// Creates a new promise whose function can pause at `yield_point`s to
+    /// let other tasks run.
+    ///
+    /// If a global `Runtime` is installed the function is queued on it, and
+    /// each `yield_point` sends it to the back of the queue so the tasks
+    /// waiting behind it get to run. This stops one long task from
+    /// monopolizing a runtime with few workers. Otherwise the function is
+    /// queued on the default runtime, like `new`, and yielding does nothing.
+    pub fn new_cooperative<F>(func: F) -> Promise<T, E>
+    where F: FnOnce(&Cooperator) -> Result<T, E>, F: Send + 'static {
+        let runtime = match Runtime::installed() {
+            Some(runtime) => runtime,
+            None => return Promise::new(move || func(&Cooperator::detached()))
+        };
+        let (tx, rx) = channel();
+        let panic = PanicSlot::default();
+        let worker_panic = panic.clone();
+        let timing = TimingSlot::default();
+        let task_timing = timing.clone();
+        let queued = Instant::now();
+
+        runtime.execute_cooperative(move |cooperator| {
+            watched(&worker_panic, tx, |tx| {
+                let result = limit::run(|| {
+                    profile::timed(&task_timing, queued, || func(cooperator))
+                });
+                tx.send(result).unwrap_or(());
+            });
+        });
+
+        let mut promise = Promise::with_panic(rx, panic);
+        promise.timing = Some(timing);
+        promise
+    }
+
+    /// Creates a new promise whose function runs on one particular worker of
+    /// the global `Runtime`.
+    ///
+    /// Functions pinned to the same worker run one after another on the same
+    /// thread, so they can share thread-confined state such as a database
+    /// handle kept in a thread local.
+    ///
+    /// # Panics
+    /// Panics if no runtime is installed, or if `worker` doesn't belong to
+    /// the installed runtime.
+    pub fn new_pinned<F>(worker: WorkerId, func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let runtime = Runtime::installed()
+            .expect("new_pinned requires an installed runtime");
+
+        let (promise, task) = Promise::queued(func);
+        runtime.execute_on(worker, task);
+        promise
+    }
+
+    /// Creates a new promise whose function can be cancelled by the crate's
+    /// combinators, such as `race_cancel`.
+    ///
+    /// The function is given a `CancelFlag`, which it should check while it
+    /// runs. Whatever it returns after being cancelled is still sent on.
+    ///
+    /// The function gets a thread of its own, as with `new_blocking`. It may
+    /// run until it's told to stop, and whatever stops it, such as a race or
+    /// a deadline, has to get to run in the meantime.
+    pub fn new_cancellable<F>(func: F) -> Promise<T, E>
+    where F: FnOnce(&CancelFlag) -> Result<T, E>, F: Send + 'static {
+        let flag = CancelFlag::new();
+        let worker_flag = flag.clone();
+
+        let mut promise = Promise::new_blocking(move || func(&worker_flag));
+        promise.cancel = Some(flag);
+        promise
+    }
+
+    /// Creates a new cancellable promise, along with a guard which cancels
+    /// the function when it is dropped.
+    pub fn new_guarded<F>(func: F) -> (Promise<T, E>, CancelGuard)
+    where F: FnOnce(&CancelFlag) -> Result<T, E>, F: Send + 'static {
+        let flag = CancelFlag::new();
+        let guard = CancelGuard::new(flag.clone());
+        let worker_flag = flag.clone();
+
+        let mut promise = Promise::new_blocking(move || func(&worker_flag));
+        promise.cancel = Some(flag);
+        (promise, guard)
+    }
+
+    /// Creates a new cancellable promise, along with a token for cancelling
+    /// it from anywhere.
+    ///
+    /// If the promise has been cancelled by the time its function returns,
+    /// it rejects with `Cancelled` whatever the function returned, so a
+    /// function can simply bail out once its `CancelFlag` is set.
+    pub fn new_with_token<F>(func: F) -> (Promise<T, E>, CancelToken)
+    where F: FnOnce(&CancelFlag) -> Result<T, E>, F: Send + 'static,
+    E: From<Cancelled> {
+        let flag = CancelFlag::new();
+        let token = CancelToken::new(flag.clone());
+        let worker_flag = flag.clone();
+
+        let mut promise = Promise::new_blocking(move || {
+            let result = func(&worker_flag);
+            if worker_flag.is_cancelled() {
+                return Err(Cancelled.into());
             }
-        }
+            result
+        });
+        promise.cancel = Some(flag);
+        (promise, token)
     }
 
-    // Static methods
+    /// A token for cancelling the promise's function, if it is cancellable,
+    /// as with those from `new_cancellable` and `new_guarded`.
+    ///
+    /// Whatever the function returns after being cancelled is still sent on.
+    pub fn cancel_token(&self) -> Option<CancelToken> {
+        self.cancel.clone().map(CancelToken::new)
+    }
 
-    fn impl_race(tx: Sender<Result<T, E>>,
-                 mut recs: Vec<Receiver<Result<T, E>>>) {
-        'outer: loop {
-            // Don't get stuck in an infinite loop
-            if recs.len() == 0 { return; }
-            for i in 0..recs.len() {
-                match recs[i].try_recv() {
-                    Ok(val) => {
-                        tx.send(val).unwrap_or(());
-                        return;
-                    }
-                    Err(err) => {
-                        if err == TryRecvError::Disconnected {
-                            recs.remove(i);
-                        }
-                    }
+    /// Creates a new cancellable promise which must settle by `deadline`, or
+    /// else it rejects with `DeadlineExceeded`.
+    ///
+    /// The function's `CancelFlag` holds the deadline, and is cancelled once
+    /// the deadline passes; when that happens the function's own result is
+    /// discarded.
+    pub fn new_within<F>(deadline: Instant, func: F) -> Promise<T, E>
+    where F: FnOnce(&CancelFlag) -> Result<T, E>, F: Send + 'static,
+    E: From<DeadlineExceeded> {
+        let flag = CancelFlag::with_deadline(deadline);
+        let worker_flag = flag.clone();
+        let recv =
+            Promise::new_blocking(move || func(&worker_flag)).into_channel();
+        let (tx, rx) = channel();
+
+        let watchdog_flag = flag.clone();
+        watchdog(move || {
+            Promise::impl_within(tx, recv, watchdog_flag, deadline);
+        });
+
+        let mut promise = Promise::from_channel(rx);
+        promise.cancel = Some(flag);
+        promise
+    }
+
+    /// Creates a new promise which calls `func` repeatedly on one worker,
+    /// starting with `init`.
+    ///
+    /// Each call returns `Continue` with the value for the next call, or
+    /// `Break` with the value to resolve to. An error stops the loop and
+    /// rejects the promise. This suits polling a job until it is done.
+    pub fn repeat_until<F>(init: T, func: F) -> Promise<T, E>
+    where F: Fn(T) -> Result<ControlFlow<T, T>, E>, F: Send + 'static {
+        Promise::new(move || {
+            let mut state = init;
+            loop {
+                match func(state)? {
+                    ControlFlow::Continue(next) => state = next,
+                    ControlFlow::Break(done) => return Ok(done)
                 }
             }
-        }
+        })
     }
 
-    fn impl_all(tx: Sender<Result<Vec<T>, E>>,
-                recs: Vec<Receiver<Result<T, E>>>) {
-        let mut values: Vec<T> = Vec::with_capacity(recs.len());
-        let mut mut_receivers = recs;
-        'outer: loop {
-            for i in 0..mut_receivers.len() {
-                match mut_receivers[i].try_recv() {
-                    Ok(val) => {
-                        match val {
-                            Ok(t) => values.push(t),
-                            Err(e) => {
-                                tx.send(Err(e)).unwrap_or(());
-                                return;
-                            }
-                        }
-                        mut_receivers.remove(i);
-                        break;
-                    }
-                    Err(err) => {
-                        if err == TryRecvError::Disconnected {
-                            mut_receivers.remove(i);
-                            break;
-                        }
-                    }
+    /// Creates a new promise like `new`, calling `func` again each time it
+    /// errors, for flaky work like a network call.
+    ///
+    /// The function is called at most `attempts` times, and at least once.
+    /// The first success resolves the promise; if every attempt fails, the
+    /// last error rejects it. To wait between attempts, sleep in `func`
+    /// before returning the error.
+    pub fn retry<F>(attempts: usize, func: F) -> Promise<T, E>
+    where F: Fn() -> Result<T, E>, F: Send + 'static {
+        Promise::new(move || {
+            let mut attempt = 1;
+            loop {
+                match func() {
+                    Err(_) if attempt < attempts => attempt += 1,
+                    result => return result
                 }
             }
-            // Check if we are finished waiting for promises
-            // This can also happen if all promises panic
-            if mut_receivers.len() == 0 {
-                let result = Ok(values);
-                tx.send(result).unwrap_or(());
-                return; // Break from outer loop
+        })
+    }
+
+    /// Creates a new promise which calls `func` every `interval` until it
+    /// has a value, for polling something which isn't ready yet.
+    ///
+    /// `func` returns `Ok(None)` while there is nothing yet. Its first value
+    /// resolves the promise and its first error rejects it. If there is no
+    /// value after `max_attempts` calls, the promise rejects with
+    /// `DeadlineExceeded`. The function is always called at least once.
+    pub fn poll_until<F>(interval: Duration, max_attempts: usize, func: F)
+                         -> Promise<T, E>
+    where F: Fn() -> Result<Option<T>, E>, F: Send + 'static,
+    E: From<DeadlineExceeded> {
+        Promise::new_blocking(move || {
+            let mut attempt = 1;
+            loop {
+                match func()? {
+                    Some(val) => return Ok(val),
+                    None if attempt < max_attempts => attempt += 1,
+                    None => return Err(DeadlineExceeded.into())
+                }
+                thread::sleep(interval);
             }
+        })
+    }
+
+    /// Applies a promise to the first of some promises to become fulfilled.
+    pub fn race(promises: Vec<Promise<T, E>>) -> Promise<T, E> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel::<Result<T, E>>();
+        Promise::impl_race(tx, recs);
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but rejects with `on_timeout` if no promise settles
+    /// within `duration`, or if every promise dies first.
+    ///
+    /// As with `timeout`, the promises which lose keep running in the
+    /// background.
+    pub fn race_timeout(promises: Vec<Promise<T, E>>, duration: Duration,
+                        on_timeout: E) -> Promise<T, E> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        watchdog(move || {
+            Promise::impl_race_timeout(tx, recs, duration, on_timeout);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but resolves to the full outcome: which promise won and
+    /// what it settled with, or that every promise died.
+    ///
+    /// The returned promise never rejects, and never dies unless its own
+    /// thread does.
+    pub fn race_outcome(promises: Vec<Promise<T, E>>)
+                        -> Promise<RaceOutcome<T, E>, Infallible> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_race_outcome(tx, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but errors don't win: resolves with the first promise to
+    /// succeed, and only rejects once every promise has failed, with the
+    /// last error to arrive.
+    ///
+    /// Promises which panic are skipped. If none of the promises settle at
+    /// all, the returned promise dies too.
+    pub fn race_ok(promises: Vec<Promise<T, E>>) -> Promise<T, E> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_race_ok(tx, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Resolves with the first of the promises to succeed. If every promise
+    /// rejects, rejects with all of their errors, in input order.
+    ///
+    /// Promises which panic are skipped and have no error in the list, so
+    /// an empty list of promises rejects straight away with no errors.
+    pub fn any(promises: Vec<Promise<T, E>>) -> Promise<T, Vec<E>> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_any(tx, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but each promise comes with a tag, and the first to
+    /// settle resolves to its tag along with its result.
+    ///
+    /// The returned promise doesn't reject on its own: a winner which errors
+    /// is reported through the `Result` next to its tag.
+    pub fn race_tagged<K>(promises: Vec<(K, Promise<T, E>)>)
+                          -> Promise<(K, Result<T, E>), E>
+    where K: Send + 'static {
+        let (tags, recs): (Vec<K>, Vec<_>) = promises.into_iter()
+            .map(|(tag, promise)| (tag, promise.into_channel()))
+            .unzip();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_race_tagged(tx, tags, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but if the first promise to settle is an error, waits up
+    /// to `window` longer for one of the others to succeed.
+    ///
+    /// The first success within the window wins. If none arrives, the first
+    /// error is used. A successful first result is returned immediately, so
+    /// the extra latency is only paid on errors.
+    pub fn race_window(promises: Vec<Promise<T, E>>, window: Duration)
+                       -> Promise<T, E> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        watchdog(move || {
+            Promise::impl_race_window(tx, recs, window);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `race`, but once there is a winner the losers are cancelled, and
+    /// the returned promise only settles after every loser has stopped.
+    ///
+    /// Losers created with `new_cancellable` have their flag set; any others
+    /// are simply waited on. A cancellable function (with any finalizer it
+    /// runs before returning) has stopped once its promise settles, so no
+    /// loser is still running when the winner's result is observed.
+    pub fn race_cancel(promises: Vec<Promise<T, E>>) -> Promise<T, E> {
+        let flags = promises.iter().map(|p| p.cancel.clone()).collect();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_race_cancel(tx, recs, flags);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Resolves to the first `n` values produced by the promises, in the
+    /// order they resolved, then cancels the promises still running if they
+    /// were created with `new_cancellable`.
+    ///
+    /// Errors are ignored while `n` values are still possible. Once too many
+    /// promises have failed, the returned promise rejects with the error
+    /// which made `n` values impossible. If it became impossible because
+    /// functions died, the returned promise dies as well.
+    ///
+    /// # Panics
+    /// Panics if `n` is greater than the number of promises, since `n`
+    /// values could never arrive.
+    pub fn take_n(promises: Vec<Promise<T, E>>, n: usize)
+                  -> Promise<Vec<T>, E> {
+        assert!(n <= promises.len(),
+                "take_n can't take {} values from {} promises",
+                n, promises.len());
+        let flags = promises.iter().map(|p| p.cancel.clone()).collect();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_take_n(tx, recs, flags, n);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Calls a function with the values of the promises that resolved by
+    /// `deadline`, or the error of the first promise to error before then.
+    ///
+    /// Values are in input order, with `None` for each promise which hadn't
+    /// settled by the deadline or whose function died. The promises still
+    /// running when the returned promise settles are cancelled if they were
+    /// created with `new_cancellable`.
+    pub fn all_deadline(promises: Vec<Promise<T, E>>, deadline: Instant)
+                        -> Promise<Vec<Option<T>>, E> {
+        let flags = promises.iter().map(|p| p.cancel.clone()).collect();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        watchdog(move || {
+            Promise::impl_all_deadline(tx, recs, flags, deadline);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Calls a function with the result of all of the promises, or the error
+    /// of the first promise to error.
+    ///
+    /// The values are in input order, whatever order the promises settle in,
+    /// with one for each promise. If a promise dies instead, so does the
+    /// returned promise. Waiting on it then reports the promise's panic as
+    /// `PromiseError::Panicked`, or `PromiseError::Dead` for a promise
+    /// which kept no record of it.
+    pub fn all(promises: Vec<Promise<T, E>>) -> Promise<Vec<T>, E> {
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let receivers =
+            promises.into_iter().map(|p| p.into_channel()).collect();
+        // Promises which have already settled, like those from `from_result`,
+        // are taken straight away rather than relayed by a thread each.
+        let (slots, indices, pending) =
+            match Promise::take_settled(receivers) {
+                Ok(split) => split,
+                Err(err) => return Promise::from_result(Err(err))
+            };
+        if pending.is_empty() {
+            let values = slots.into_iter().flatten().collect();
+            return Promise::from_result(Ok(values));
+        }
+        let (tx, rx) = channel();
+        Promise::impl_all_settled(tx, slots, indices, pending, batch);
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Like `all`, but the promises become children of `parent`, so
+    /// cancelling or dropping the scope cancels the whole batch.
+    ///
+    /// Cancelled children settle with whatever their functions return, so a
+    /// child which errors on cancellation rejects the batch as usual.
+    pub fn all_child_of(parent: &PromiseScope, promises: Vec<Promise<T, E>>)
+                        -> Promise<Vec<T>, E> {
+        for promise in &promises {
+            if let Some(ref flag) = promise.cancel {
+                parent.adopt(flag.clone());
+            }
+        }
+        Promise::all(promises)
+    }
+
+    /// Creates a promise from each of the functions and calls a function
+    /// with all of their results, as with `all`.
+    ///
+    /// Each function runs on a thread named `batch-<label>-<index>`, so it
+    /// can be told apart in backtraces and panic messages.
+    pub fn all_from_fns_named<F>(label: &str, makers: Vec<F>)
+                                 -> Promise<Vec<T>, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let promises = makers.into_iter().enumerate().map(|(index, func)| {
+            let (tx, rx) = channel();
+            let slot = PanicSlot::default();
+            let worker_slot = slot.clone();
+            let handle = thread::Builder::new()
+                .name(format!("batch-{}-{}", label, index))
+                .spawn(move || {
+                    watched(&worker_slot, tx, |tx| Promise::impl_new(tx, func));
+                })
+                .expect("failed to spawn thread");
+            let mut promise = Promise::with_handle(rx, handle);
+            promise.panic = Some(slot);
+            promise
+        }).collect();
+
+        Promise::all(promises)
+    }
+
+    /// Runs the functions with at most `max_concurrent` of them at a time,
+    /// and calls a function with all of their results, as with `all`.
+    ///
+    /// The rest wait in a queue, and start in input order as earlier ones
+    /// finish. This bounds the workers, and whatever the functions hold such
+    /// as sockets, a large batch takes up. The values are in input order.
+    /// Once a function errors the batch rejects, and the queued functions are
+    /// skipped. If a function panics, the batch dies with its message.
+    ///
+    /// # Panics
+    /// Panics if `max_concurrent` is zero.
+    pub fn all_limited<F>(funcs: Vec<F>, max_concurrent: usize)
+                          -> Promise<Vec<T>, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        assert!(max_concurrent > 0, "all_limited needs to run at least one \
+                                     function at a time");
+        let count = funcs.len();
+        let runtime = Runtime::new(max_concurrent.min(count).max(1));
+        let failed = Arc::new(AtomicBool::new(false));
+        let panic = PanicSlot::default();
+        let (results_tx, results_rx) = channel();
+
+        for (index, func) in funcs.into_iter().enumerate() {
+            let results_tx = results_tx.clone();
+            let failed = failed.clone();
+            let panic = panic.clone();
+            runtime.execute(Box::new(move || {
+                // The batch has already rejected, so the result isn't wanted
+                if failed.load(Ordering::SeqCst) {
+                    return;
+                }
+                watched(&panic, results_tx, |tx| {
+                    let result = func();
+                    let rejected = result.is_err();
+                    // The error is sent before the flag is set, so it is
+                    // already queued by the time skipped functions go quiet
+                    tx.send((index, result)).unwrap_or(());
+                    if rejected {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                });
+            }));
+        }
+        drop(results_tx);
+
+        // The workers finish the queue before exiting
+        let (tx, rx) = channel();
+        relay(move || {
+            Promise::impl_all_limited(tx, results_rx, count);
+        });
+
+        Promise::with_panic(rx, panic)
+    }
+
+    /// Like `all`, but also summarizes how long the promises took to settle,
+    /// measured from this call.
+    ///
+    /// The values are in input order. If a promise dies, so does the
+    /// returned promise, as with `all`.
+    pub fn latency_all(promises: Vec<Promise<T, E>>)
+                       -> Promise<(Vec<T>, LatencyStats), E> {
+        let start = Instant::now();
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_latency_all(tx, recs, start, batch);
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Combines the values of all of the promises with `func`, or rejects
+    /// with the error of the first promise to error.
+    ///
+    /// Values are combined pairwise as soon as two are available, each
+    /// combination a task of its own on the global runtime, so a large batch
+    /// is reduced as a tree
+    /// rather than one value at a time. The order in which values are
+    /// combined depends on when they arrive, so `func` must be associative
+    /// and commutative to give a predictable result.
+    ///
+    /// If a promise dies, or `func` panics, the returned promise dies with
+    /// the panic's message, as with `all`. It also dies if there are no
+    /// values at all.
+    pub fn reduce_parallel<F>(promises: Vec<Promise<T, E>>, func: F)
+                              -> Promise<T, E>
+    where F: Fn(T, T) -> T + Clone + Send + 'static {
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_reduce_parallel(tx, recs, func, batch);
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Like `all`, but leaves out values equal to an earlier one, for
+    /// gathering from redundant sources.
+    ///
+    /// The values are in input order, keeping the first of each duplicate.
+    /// If a promise dies, so does the returned promise.
+    pub fn all_dedup(promises: Vec<Promise<T, E>>) -> Promise<Vec<T>, E>
+    where T: Hash + Eq {
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_dedup(tx, recs, batch);
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Like `all`, but once the values held in memory would take up more than
+    /// `spill_threshold` bytes, the rest are written to a temporary file.
+    ///
+    /// Sizes are estimated with `Spill::spill_size`. The result yields the
+    /// values in the order they resolved, reading spilled values back from
+    /// disk. A failure to write the file rejects the returned promise, and a
+    /// promise which dies kills it.
+    pub fn all_spilled(promises: Vec<Promise<T, E>>, spill_threshold: usize)
+                       -> Promise<SpillResult<T>, E>
+    where T: Spill, E: From<io::Error> {
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_spilled(tx, recs, spill_threshold, batch);
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Waits for every promise and resolves to each one's result, in input
+    /// order. It never rejects.
+    ///
+    /// A promise which died without settling gives `PromiseError::Dead`, so
+    /// every input has an entry.
+    pub fn all_settled(promises: Vec<Promise<T, E>>)
+                       -> Promise<Vec<Result<T, PromiseError<E>>>, Infallible> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_settled_results(tx, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Waits for every promise and reports how each one settled, with how
+    /// long it took measured from this call.
+    ///
+    /// Unlike `all`, this never stops early: the report has an outcome for
+    /// every promise, in input order, along with the totals of each kind.
+    pub fn all_report(promises: Vec<Promise<T, E>>)
+                      -> Promise<AllReport<T, E>, Infallible> {
+        let start = Instant::now();
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_report(tx, recs, start);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Waits for every promise and counts how many resolved, rejected and
+    /// died, discarding their values. This suits health checks over a fan
+    /// out of work, where `all_report` would keep more than is needed.
+    pub fn tally(promises: Vec<Promise<T, E>>) -> Promise<Tally, Infallible> {
+        let recs = promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_tally(tx, recs);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `all`, but the error says which promise failed, including one
+    /// which panicked: that rejects with `AllError::Panicked` rather than
+    /// killing the batch.
+    ///
+    /// The values are in input order.
+    pub fn all_detailed(promises: Vec<Promise<T, E>>)
+                        -> Promise<Vec<T>, AllError<E>> {
+        let receivers =
+            promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_detailed(tx, receivers);
+        });
+
+        Promise::from_channel(rx)
+    }
+
+    /// Like `all`, but if any promise errors the successful values collected
+    /// so far are kept alongside the error, in input order.
+    ///
+    /// "So far" depends on timing: promises which had not settled when the
+    /// error arrived are missing from the partial values, even if they would
+    /// have succeeded. As with `all`, a promise which dies kills the batch.
+    pub fn all_partial(promises: Vec<Promise<T, E>>)
+                       -> Promise<Vec<T>, (E, Vec<T>)> {
+        let batch = BatchPanic::of(&promises, PanicSlot::default());
+        let slot = batch.output.clone();
+        let receivers =
+            promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = channel();
+
+        relay(move || {
+            Promise::impl_all_partial(tx, receivers, batch);
+        });
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Streams the result of each promise as soon as it settles, so results
+    /// can be handled without waiting for the slowest promise.
+    ///
+    /// The results are in the order the promises settled. Promises which
+    /// panic are skipped, and the stream ends once every promise has settled
+    /// or died.
+    pub fn stream(promises: Vec<Promise<T, E>>) -> PromiseStream<T, E> {
+        let (tx, rx) = mpsc::channel();
+        for promise in promises {
+            let recv = promise.into_channel();
+            let tx = tx.clone();
+            notify::when_settled(recv, move |recv| {
+                if let Ok(message) = recv.recv() {
+                    tx.send(message).unwrap_or(());
+                }
+            });
+        }
+        // The stream ends once the last promise drops its sender
+        PromiseStream::from_receiver(rx)
+    }
+
+    /// Streams the result of each promise in input order, followed by a
+    /// final `StreamEvent::Done` once every promise has settled.
+    ///
+    /// Results which arrive early are held back until the promises before
+    /// them have settled. Promises which panic are skipped and counted in the
+    /// `dead` total of the `Done` event, so a stream which ends early can be
+    /// told apart from one which is just slow.
+    pub fn ordered_stream_all_terminated(
+        promises: Vec<Promise<T, E>>) -> mpsc::Receiver<StreamEvent<T, E>> {
+        let receivers =
+            promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = mpsc::channel();
+
+        relay(move || {
+            Promise::impl_ordered_stream_terminated(tx, receivers);
+        });
+
+        rx
+    }
+
+    /// Streams the result of each promise in input order, like
+    /// `ordered_stream_all_terminated`, while reporting progress as they
+    /// settle.
+    ///
+    /// `on_progress` is called with the number of promises settled so far and
+    /// the total each time one settles, in whatever order they finish and
+    /// before any result it releases is streamed. Promises which panic count
+    /// towards the progress but are skipped in the stream.
+    pub fn ordered_stream_all_progress<F>(promises: Vec<Promise<T, E>>,
+                                          on_progress: F)
+                                          -> PromiseStream<T, E>
+    where F: FnMut(usize, usize) + Send + 'static {
+        let receivers =
+            promises.into_iter().map(|p| p.into_channel()).collect();
+        let (tx, rx) = mpsc::channel();
+
+        relay(move || {
+            Promise::impl_ordered_stream_progress(tx, receivers, on_progress);
+        });
+
+        PromiseStream::from_receiver(rx)
+    }
+
+    /// Like `all`, but also hands back the `JoinHandle` of each promise's
+    /// worker thread, in input order.
+    ///
+    /// Joining a handle returns the panic payload of a worker which panicked,
+    /// which the promise itself cannot convey. Only promises created with
+    /// `new_blocking` or `then_named` own their worker thread; the others
+    /// have `None`.
+    #[allow(clippy::type_complexity)]
+    pub fn all_with_handles(mut promises: Vec<Promise<T, E>>)
+                            -> (Promise<Vec<T>, E>, Vec<Option<JoinHandle<()>>>) {
+        let handles = promises.iter_mut().map(|p| p.handle.take()).collect();
+        (Promise::all(promises), handles)
+    }
+
+    /// Waits for this promise and `other`, resolving to both values, or the
+    /// first error from either. This is the building block of `all!`.
+    #[doc(hidden)]
+    pub fn __all_pair<B>(self, other: Promise<B, E>) -> Promise<(T, B), E>
+    where B: Send + 'static {
+        let batch = BatchPanic {
+            inputs: vec![self.panic.clone(), other.panic.clone()],
+            output: PanicSlot::default()
+        };
+        let slot = batch.output.clone();
+        let first = self.into_channel();
+        let second = other.into_channel();
+        let (tx, rx) = channel();
+
+        Promise::impl_pair(tx, first, second, batch);
+
+        Promise::with_panic(rx, slot)
+    }
+
+    /// Makes the promise's result available to any number of subscribers,
+    /// each of which gets a copy of it as a promise of its own.
+    pub fn shared(self) -> SharedPromise<T, E>
+    where T: Clone, E: Clone {
+        SharedPromise::new(self)
+    }
+
+    /// Takes the `JoinHandle` of the promise's worker thread, if the promise
+    /// owns one, as with `all_with_handles`. Later calls return `None`.
+    pub fn take_handle(&mut self) -> Option<JoinHandle<()>> {
+        self.handle.take()
+    }
+
+    /// Blocks until any one of the given promises is ready, returning its
+    /// index. No values are consumed: every promise can still be chained
+    /// afterwards, and the one at the returned index will not block.
+    ///
+    /// A promise whose function panicked counts as ready, as waiting on it
+    /// any longer would never produce a value. If several are ready, the
+    /// lowest index is returned, so if every promise is dead the first one
+    /// is. Waiting doesn't start any threads, other than to relay a promise
+    /// made by `from_receiver`, as with awaiting one.
+    ///
+    /// # Panics
+    /// Panics if `promises` is empty, since none of them could ever become
+    /// ready.
+    pub fn ready_on(promises: &[Promise<T, E>]) -> usize {
+        assert!(!promises.is_empty(),
+                "ready_on requires at least one promise to wait on");
+        let waker = notify::unparker(thread::current());
+        for promise in promises {
+            promise.receiver().listen(&waker);
+        }
+        // A promise which settled before its waker was left is found here,
+        // and any later one unparks the thread
+        let ready = runtime::blocking(|| loop {
+            let ready = promises.iter().position(|promise| {
+                promise.try_settle() != PromiseState::Pending
+            });
+            match ready {
+                Some(index) => break index,
+                None => thread::park()
+            }
+        });
+        for promise in promises {
+            if let Inner::Channel(ref rx) = *promise.inner.borrow() {
+                rx.unlisten();
+            }
+        }
+        ready
+    }
+
+    /// Returns a file descriptor which becomes readable once the promise
+    /// settles or its function dies, for waiting on the promise from a
+    /// `poll`/`epoll` based event loop.
+    ///
+    /// The descriptor belongs to the promise and is closed when the promise
+    /// is consumed or dropped. Calling this again returns the same
+    /// descriptor.
+    #[cfg(all(unix, feature = "readiness-fd"))]
+    pub fn readiness_fd(&self) -> RawFd {
+        let mut readiness = self.readiness.borrow_mut();
+        if let Some(ref reader) = *readiness {
+            return reader.as_raw_fd();
+        }
+        let (reader, writer) = io::pipe().expect("failed to create pipe");
+        let (tx, rx) = channel();
+        let recv = mem::replace(&mut *self.receiver(), rx);
+
+        Runtime::global().execute_after(recv, move |recv| {
+            Promise::impl_readiness(tx, recv, writer);
+        });
+
+        readiness.get_or_insert(reader).as_raw_fd()
+    }
+
+    /// Blocks the current thread until the promise settles, returning its
+    /// value or error.
+    ///
+    /// This is for the edges of a program, such as `main` or a test, which
+    /// need the result itself rather than another promise. If the promise's
+    /// function died without settling, the error says so rather than waiting
+    /// forever: `PromiseError::Panicked` with the panic's message for
+    /// promises from `new` and the `then` family of methods chained on them,
+    /// and `PromiseError::Dead` otherwise.
+    pub fn wait(mut self) -> Result<T, PromiseError<E>> {
+        match self.take_result() {
+            Ok(result) => result.map_err(PromiseError::Rejected),
+            Err(_) => Err(Promise::<T, E>::death(self.panic.take()))
+        }
+    }
+
+    /// Blocks the current thread until the promise settles, like `wait`, but
+    /// for no longer than `timeout`.
+    ///
+    /// The outer `Result` says whether the promise settled in time; the inner
+    /// one is the promise's own result. The promise is consumed either way,
+    /// and its function carries on in the background after a timeout.
+    pub fn wait_timeout(mut self, timeout: Duration)
+                        -> Result<Result<T, E>, TimeoutError> {
+        let panic = self.panic.take();
+        let received =
+            runtime::blocking(|| self.receiver().recv_timeout(timeout));
+        match received {
+            Ok(result) => Ok(result),
+            Err(RecvTimeoutError::Timeout) => Err(TimeoutError::TimedOut),
+            Err(RecvTimeoutError::Disconnected) => {
+                match Promise::<T, E>::death(panic) {
+                    PromiseError::Panicked(message) => {
+                        Err(TimeoutError::Panicked(message))
+                    }
+                    _ => Err(TimeoutError::Dead)
+                }
+            }
+        }
+    }
+
+    /// Checks whether the promise has settled without blocking, returning a
+    /// copy of its result if it has.
+    ///
+    /// The result stays in the promise, so it can be checked again or
+    /// chained afterwards as usual. A promise whose function died gives the
+    /// same error as `wait`.
+    pub fn try_wait(&self) -> Option<Result<T, PromiseError<E>>>
+    where T: Clone, E: Clone {
+        let result = match self.try_settle() {
+            PromiseState::Pending => return None,
+            PromiseState::Resolved => match *self.inner.borrow() {
+                Inner::Ready(ref result) => result.clone(),
+                _ => unreachable!("a settled promise holds its result")
+            },
+            PromiseState::Dead => {
+                return Some(Err(Promise::<T, E>::death(self.panic.clone())));
+            }
+        };
+        Some(result.map_err(PromiseError::Rejected))
+    }
+
+    /// Checks whether the promise has settled, without blocking or taking
+    /// its result.
+    ///
+    /// A promise reported as `Resolved` will not block when chained or
+    /// waited on. This suits progress displays which poll many promises.
+    pub fn state(&self) -> PromiseState {
+        let mut inner = self.inner.borrow_mut();
+        let settled = match *inner {
+            Inner::Channel(ref rx) => rx.try_recv(),
+            Inner::Ready(_) => return PromiseState::Resolved,
+            Inner::Taken => return PromiseState::Dead
+        };
+        match settled {
+            Ok(result) => {
+                // Keep the result in the promise for later
+                *inner = Inner::Ready(result);
+                PromiseState::Resolved
+            }
+            Err(TryRecvError::Empty) => PromiseState::Pending,
+            Err(TryRecvError::Disconnected) => PromiseState::Dead
+        }
+    }
+
+    /// Whether the promise has settled, with either a value or an error.
+    /// This is `state` for when a dead promise doesn't need telling apart
+    /// from a pending one.
+    pub fn is_resolved(&self) -> bool {
+        self.state() == PromiseState::Resolved
+    }
+
+    /// How long the promise's function waited for a `Runtime` worker and
+    /// then ran, once it has finished.
+    ///
+    /// Only promises whose functions are queued on a runtime, such as those
+    /// of `new_cooperative` and `new_pinned`, have a timing. This tells a
+    /// slow function apart from a saturated runtime.
+    pub fn last_timing(&self) -> Option<TaskTiming> {
+        let slot = self.timing.as_ref()?;
+        *slot.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Creates a promise that resolves to a value, without a thread or
+    /// channel. See `ReadyPromise`.
+    pub fn resolve(val: T) -> ReadyPromise<T, E> {
+        ReadyPromise::resolve(val)
+    }
+
+    /// Creates a promise that resolves to an error, without a thread or
+    /// channel. See `ReadyPromise`.
+    pub fn reject(val: E) -> ReadyPromise<T, E> {
+        ReadyPromise::reject(val)
+    }
+
+    /// Creates a new promise that will resolve to the result value.
+    pub fn from_result(result: Result<T, E>) -> Promise<T, E> {
+        // The result is kept in place, with no channel until one is needed
+        Promise::with_inner(Inner::Ready(result), None)
+    }
+
+    /// Creates a promise that resolves to a value once `duration` has passed.
+    ///
+    /// The wait happens on a thread of the promise's own, as with
+    /// `new_blocking`, not the caller's.
+    pub fn delay(duration: Duration, val: T) -> Promise<T, E> {
+        Promise::delay_with(duration, move || Ok(val))
+    }
+
+    /// Creates a new promise like `new`, whose function is only called once
+    /// `duration` has passed. This is the building block for scheduled work,
+    /// such as a retry after a backoff.
+    pub fn delay_with<F>(duration: Duration, func: F) -> Promise<T, E>
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        Promise::new_blocking(move || {
+            thread::sleep(duration);
+            func()
+        })
+    }
+
+    /// Creates a promise that is already settled to the result value,
+    /// without a thread or channel. See `ReadyPromise`.
+    pub fn ready(result: Result<T, E>) -> ReadyPromise<T, E> {
+        ReadyPromise::from_result(result)
+    }
+
+    /// Creates a ready promise holding the value inline, for seeding a
+    /// pipeline with a literal. Nothing is allocated, and this can be used
+    /// to initialize a `const`.
+    pub const fn constant(val: T) -> ReadyPromise<T, E> {
+        ReadyPromise::resolve(val)
+    }
+
+    /// Creates a new promise that will resolve to a copy of the result value.
+    pub fn from_result_ref(result: &Result<T, E>) -> Promise<T, E>
+    where T: Clone, E: Clone {
+        Promise::from_result(result.clone())
+    }
+
+    /// Creates a promise which settles with the first result sent into a
+    /// channel, for wrapping channels from elsewhere without another thread.
+    ///
+    /// The promise dies if every sender is dropped without sending.
+    pub fn from_receiver(rx: mpsc::Receiver<Result<T, E>>) -> Promise<T, E> {
+        Promise::with_parts(Receiver::unwoken(rx), None)
+    }
+
+    /// Takes the channel the promise will settle through, for driving it by
+    /// hand.
+    ///
+    /// The channel disconnects if the promise dies, but carries no panic
+    /// message as `wait` would report.
+    pub fn into_receiver(self) -> mpsc::Receiver<Result<T, E>> {
+        self.into_channel().into_inner()
+    }
+
+    // Implementation Functions
+
+    /// A promise settling through a channel from `notify::channel`, which
+    /// can be awaited without relaying it.
+    fn from_channel(rx: Receiver<Result<T, E>>) -> Promise<T, E> {
+        Promise::with_parts(rx, None)
+    }
+
+    /// Takes the channel the promise will settle through, for chaining onto
+    /// it.
+    fn into_channel(self) -> Receiver<Result<T, E>> {
+        self.demand();
+        self.inner.into_inner().into_receiver()
+    }
+
+    /// The channel the promise settles through, made for it first if its
+    /// result is held in place. This starts a `lazy` promise.
+    fn receiver(&self) -> RefMut<'_, Receiver<Result<T, E>>> {
+        self.demand();
+        let mut inner = self.inner.borrow_mut();
+        if let Inner::Channel(_) = *inner { } else {
+            let held = mem::replace(&mut *inner, Inner::Taken);
+            *inner = Inner::Channel(held.into_receiver());
+        }
+        RefMut::map(inner, |inner| match *inner {
+            Inner::Channel(ref mut rx) => rx,
+            _ => unreachable!("the promise was just given a channel")
+        })
+    }
+
+    /// Blocks until the promise settles and takes its result, leaving the
+    /// promise dead. This starts a `lazy` promise.
+    fn take_result(&self) -> Result<Result<T, E>, RecvError> {
+        self.demand();
+        let mut inner = self.inner.borrow_mut();
+        match mem::replace(&mut *inner, Inner::Taken) {
+            Inner::Ready(result) => Ok(result),
+            Inner::Taken => Err(RecvError),
+            Inner::Channel(rx) => {
+                let result = runtime::blocking(|| rx.recv());
+                *inner = Inner::Channel(rx);
+                result
+            }
+        }
+    }
+
+    /// Moves the result into the promise if it has arrived, without
+    /// blocking, and reports whether it has. This starts a `lazy` promise.
+    fn try_settle(&self) -> PromiseState {
+        self.demand();
+        self.state()
+    }
+
+    /// Starts the work of a `lazy` promise, if it hasn't started yet.
+    fn demand(&self) {
+        let start = self.start.borrow_mut().take();
+        if let Some(start) = start {
+            start();
+        }
+    }
+
+    fn with_handle(rx: Receiver<Result<T, E>>, handle: JoinHandle<()>)
+                   -> Promise<T, E> {
+        Promise::with_parts(rx, Some(handle))
+    }
+
+    fn with_parts(rx: Receiver<Result<T, E>>,
+                  handle: Option<JoinHandle<()>>) -> Promise<T, E> {
+        Promise::with_inner(Inner::Channel(rx), handle)
+    }
+
+    fn with_inner(inner: Inner<T, E>, handle: Option<JoinHandle<()>>)
+                  -> Promise<T, E> {
+        Promise {
+            inner: RefCell::new(inner),
+            handle,
+            cancel: None,
+            timing: None,
+            panic: None,
+            runtime: None,
+            start: RefCell::new(None),
+            #[cfg(all(unix, feature = "readiness-fd"))]
+            readiness: RefCell::new(None)
+        }
+    }
+
+    /// A promise chained onto one with the given panic slot, which it shares
+    /// so a panic anywhere up the chain is reported by the end of it.
+    fn with_panic(rx: Receiver<Result<T, E>>, slot: PanicSlot)
+                  -> Promise<T, E> {
+        let mut promise = Promise::from_channel(rx);
+        promise.panic = Some(slot);
+        promise
+    }
+
+    /// The promise's panic slot, or a new one for a chain which starts here.
+    fn panic_slot(&self) -> PanicSlot {
+        self.panic.clone().unwrap_or_default()
+    }
+
+    /// Why the promise died: the panic recorded by its worker, if any.
+    fn death(panic: Option<PanicSlot>) -> PromiseError<E> {
+        match panic.as_ref().and_then(|slot| slot.get()) {
+            Some(message) => PromiseError::Panicked(message.clone()),
+            None => PromiseError::Dead
+        }
+    }
+
+    /// A promise for a function queued on a runtime, and the task which runs
+    /// it. As with `new`, the function counts towards the global concurrency
+    /// cap and a panic is kept for `wait`. The task also records how long
+    /// the function waited, including for the cap, and ran.
+    fn queued<F>(func: F) -> (Promise<T, E>, WorkItem)
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let (tx, rx) = channel();
+        let panic = PanicSlot::default();
+        let worker_panic = panic.clone();
+        let timing = TimingSlot::default();
+        let task_timing = timing.clone();
+        let queued = Instant::now();
+
+        let task: WorkItem = Box::new(move || {
+            watched(&worker_panic, tx, |tx| {
+                let result = limit::run(|| {
+                    profile::timed(&task_timing, queued, func)
+                });
+                tx.send(result).unwrap_or(());
+            });
+        });
+
+        let mut promise = Promise::with_panic(rx, panic);
+        promise.timing = Some(timing);
+        (promise, task)
+    }
+
+    fn impl_new<F>(tx: Sender<Result<T, E>>, func: F)
+    where F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        let result = limit::run(func);
+        tx.send(result).unwrap_or(());
+    }
+
+    fn impl_new_catch<F>(func: F) -> Result<T, E>
+    where F: FnOnce() -> Result<T, E>, E: From<PanicMessage> {
+        match panic::catch_unwind(AssertUnwindSafe(func)) {
+            Ok(result) => result,
+            Err(payload) => Err(PanicMessage::from_payload(&*payload).into())
+        }
+    }
+
+    fn impl_within(tx: Sender<Result<T, E>>, rx: Receiver<Result<T, E>>,
+                   flag: CancelFlag, deadline: Instant)
+    where E: From<DeadlineExceeded> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok(result) => tx.send(result).unwrap_or(()),
+            Err(RecvTimeoutError::Timeout) => {
+                flag.cancel();
+                tx.send(Err(DeadlineExceeded.into())).unwrap_or(());
+            }
+            Err(RecvTimeoutError::Disconnected) => { }
+        }
+    }
+
+    fn impl_until_signal<S>(tx: Sender<Result<T, TerminatedOr<E>>>,
+                            rx: Receiver<Result<T, E>>,
+                            signal: Receiver<Result<(), S>>)
+    where S: Send + 'static {
+        // The first of the two to report wins. `None` is the signal firing,
+        // and `Some(None)` the promise dying.
+        let (first_tx, first_rx) = channel();
+        let signal_tx = first_tx.clone();
+        notify::when_settled(rx, move |rx| {
+            first_tx.send(Some(rx.recv().ok())).unwrap_or(());
+        });
+        notify::when_settled(signal, move |signal| {
+            if let Ok(Ok(())) = signal.recv() {
+                signal_tx.send(None).unwrap_or(());
+            }
+        });
+
+        notify::when_settled(first_rx, move |first_rx| match first_rx.recv() {
+            Ok(Some(Some(result))) =>
+                tx.send(result.map_err(TerminatedOr::Rejected)).unwrap_or(()),
+            Ok(None) => tx.send(Err(TerminatedOr::Terminated)).unwrap_or(()),
+            // The promise died, which kills this one without waiting on the
+            // signal
+            Ok(Some(None)) | Err(_) => { }
+        });
+    }
+
+    fn impl_then<T2, E2, F1, F2>(tx: Sender<Result<T2, E2>>,
+                                 rx: Receiver<Result<T, E>>,
+                                 callback: F1, errback: F2)
+    where T2: Send + 'static, E2: Send + 'static,
+    F1: FnOnce(T) -> Result<T2, E2>, F2: FnOnce(E) -> Result<T2, E2>,
+    F1: Send + 'static, F2: Send + 'static
+    {
+        if let Ok(message) = rx.recv() {
+            match message {
+                Ok(val) =>
+                    tx.send(limit::run(|| callback(val))).unwrap_or(()),
+                Err(err) =>
+                    tx.send(limit::run(|| errback(err))).unwrap_or(())
+            };
+        }
+    }
+
+    fn impl_then_result<T2, E2, F>(tx: Sender<Result<T2, E2>>,
+                                   rx: Receiver<Result<T, E>>,
+                                   resultback: F)
+    where T2: Send + 'static, E2: Send + 'static,
+    F: FnOnce(Result<T, E>) -> Result<T2, E2>, F: Send + 'static {
+
+        if let Ok(result) = rx.recv() {
+            tx.send(limit::run(|| resultback(result))).unwrap_or(());
+        }
+    }
+
+    fn impl_ok_then<T2, F>(tx: Sender<Result<T2, E>>,
+                           rx: Receiver<Result<T, E>>, callback: F)
+    where F: FnOnce(T) -> Result<T2, E>, F: Send + 'static,
+    T2: Send + 'static {
+        if let Ok(message) = rx.recv() {
+            match message {
+                Ok(val) =>
+                    tx.send(limit::run(|| callback(val))).unwrap_or(()),
+                Err(err) => tx.send(Err(err)).unwrap_or(())
+            }
+        }
+    }
+
+    fn impl_err_then<E2, F>(tx: Sender<Result<T, E2>>,
+                           rx: Receiver<Result<T, E>>, errback: F)
+    where F: FnOnce(E) -> Result<T, E2>, F: Send + 'static,
+    E2: Send + 'static {
+        if let Ok(message) = rx.recv() {
+            match message {
+                Ok(val) => tx.send(Ok(val)).unwrap_or(()),
+                Err(err) =>
+                    tx.send(limit::run(|| errback(err))).unwrap_or(())
+            }
+        }
+    }
+
+    fn impl_fan_out<U, F>(tx: Sender<Result<Vec<U>, E>>,
+                          rx: Receiver<Result<T, E>>, callback: F,
+                          slot: PanicSlot)
+    where U: Send + 'static, F: FnOnce(T) -> Vec<Promise<U, E>> {
+        if let Ok(message) = rx.recv() {
+            match message {
+                Ok(val) => {
+                    let promises = limit::run(|| callback(val));
+                    let batch = BatchPanic::of(&promises, slot);
+                    let receivers = promises.into_iter()
+                        .map(|p| p.into_channel()).collect();
+                    Promise::impl_all(tx, receivers, batch);
+                }
+                Err(err) => tx.send(Err(err)).unwrap_or(())
+            }
+        }
+    }
+
+    fn impl_and_then<T2, F>(tx: Sender<Result<T2, E>>,
+                            rx: Receiver<Result<T, E>>, callback: F,
+                            slot: &PanicSlot)
+    where T2: Send + 'static, F: FnOnce(T) -> Promise<T2, E> {
+        match rx.recv() {
+            Ok(Ok(val)) => {
+                let inner = limit::run(|| callback(val));
+                Promise::impl_pass_on(tx, inner, slot);
+            }
+            Ok(Err(err)) => tx.send(Err(err)).unwrap_or(()),
+            Err(_) => { }
+        }
+    }
+
+    /// Settles with the result of a promise started by a callback, or dies
+    /// of the same panic if it dies.
+    fn impl_pass_on(tx: Sender<Result<T, E>>, inner: Promise<T, E>,
+                    slot: &PanicSlot) {
+        let inner_slot = inner.panic.clone();
+        let slot = slot.clone();
+        notify::when_settled(inner.into_channel(), move |inner| {
+            match inner.recv() {
+                Ok(result) => tx.send(result).unwrap_or(()),
+                Err(_) => error::pass_panic(&inner_slot, &slot)
+            }
+        });
+    }
+
+    fn impl_recover_pipeline<F>(tx: Sender<Result<T, E>>,
+                                rx: Receiver<Result<T, E>>, recovery: F,
+                                slot: &PanicSlot)
+    where F: FnOnce(E) -> Promise<T, E> {
+        match rx.recv() {
+            Ok(Err(err)) => {
+                let pipeline = limit::run(|| recovery(err));
+                Promise::impl_pass_on(tx, pipeline, slot);
+            }
+            Ok(settled) => tx.send(settled).unwrap_or(()),
+            Err(_) => { }
+        }
+    }
+
+    fn impl_finally<F>(tx: Sender<Result<T, E>>, rx: Receiver<Result<T, E>>,
+                       func: F)
+    where F: FnOnce() {
+        let message = rx.recv();
+        limit::run(func);
+        if let Ok(result) = message {
+            tx.send(result).unwrap_or(());
+        }
+    }
+
+    fn impl_timeout(tx: Sender<Result<T, E>>, rx: Receiver<Result<T, E>>,
+                    flag: Option<CancelFlag>, duration: Duration,
+                    on_timeout: E) {
+        match rx.recv_timeout(duration) {
+            Ok(result) => tx.send(result).unwrap_or(()),
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(flag) = flag {
+                    flag.cancel();
+                }
+                // The receiver is dropped here, so the late result is
+                // discarded by its sender rather than causing a panic.
+                tx.send(Err(on_timeout)).unwrap_or(());
+            }
+            Err(RecvTimeoutError::Disconnected) => { }
+        }
+    }
+
+    fn impl_with_finalizer<T2, F, G>(tx: Sender<Result<T2, E>>,
+                                     rx: Receiver<Result<T, E>>,
+                                     body: F, finalize: G)
+    where T2: Send + 'static, F: FnOnce(T) -> Result<T2, E>, G: FnOnce() {
+        let result = {
+            // Dropped at the end of this block, or while unwinding out of it
+            let _guard = Finalizer { finalize: Some(finalize) };
+            match rx.recv() {
+                Ok(Ok(val)) => Some(limit::run(|| body(val))),
+                Ok(Err(err)) => Some(Err(err)),
+                Err(_) => None
+            }
+        };
+        if let Some(result) = result {
+            tx.send(result).unwrap_or(());
+        }
+    }
+
+    // Static methods
+
+    #[cfg(all(unix, feature = "readiness-fd"))]
+    fn impl_readiness(tx: Sender<Result<T, E>>, rx: Receiver<Result<T, E>>,
+                      mut writer: io::PipeWriter) {
+        if let Ok(message) = rx.recv() {
+            tx.send(message).unwrap_or(());
+        }
+        // The relayed value must be visible before the descriptor becomes
+        // readable.
+        drop(tx);
+        writer.write_all(&[1]).unwrap_or(());
+    }
+
+    /// Forwards every receiver's message into one channel, tagged with the
+    /// index of the receiver it came from, as each settles rather than from
+    /// a thread per receiver. A receiver which disconnects without a message
+    /// reports `None`. The returned channel disconnects once every receiver
+    /// has reported, so it never needs to be polled.
+    fn select(recs: Vec<Receiver<Result<T, E>>>)
+              -> Receiver<(usize, Option<Result<T, E>>)> {
+        let (tx, rx) = channel();
+        for (index, rec) in recs.into_iter().enumerate() {
+            let tx = tx.clone();
+            notify::when_settled(rec, move |rec| {
+                tx.send((index, rec.recv().ok())).unwrap_or(());
+            });
+        }
+        rx
+    }
+
+    fn impl_latency_all(tx: Sender<Result<(Vec<T>, LatencyStats), E>>,
+                        recs: Vec<Receiver<Result<T, E>>>, start: Instant,
+                        batch: BatchPanic) {
+        let mut values: Vec<Option<T>> = recs.iter().map(|_| None).collect();
+        let mut times = Vec::with_capacity(values.len());
+        for (index, message) in Promise::select(recs) {
+            match message {
+                Some(Ok(val)) => {
+                    times.push(start.elapsed());
+                    values[index] = Some(val);
+                }
+                Some(Err(err)) => {
+                    tx.send(Err(err)).unwrap_or(());
+                    return;
+                }
+                None => return batch.died(index)
+            }
+        }
+        let values = values.into_iter().flatten().collect();
+        let stats = LatencyStats::from_durations(times);
+        tx.send(Ok((values, stats))).unwrap_or(());
+    }
+
+    fn impl_reduce_parallel<F>(tx: Sender<Result<T, E>>,
+                               recs: Vec<Receiver<Result<T, E>>>, func: F,
+                               batch: BatchPanic)
+    where F: Fn(T, T) -> T + Clone + Send + 'static {
+        // Input and combined values share one channel, so whichever comes
+        // next can be paired with the value held back.
+        let (values_tx, values_rx) = channel();
+        let mut outstanding = recs.len();
+        for (index, rec) in recs.into_iter().enumerate() {
+            let values_tx = values_tx.clone();
+            notify::when_settled(rec, move |rec| {
+                values_tx.send(Reduction::Settled(index, rec.recv().ok()))
+                    .unwrap_or(());
+            });
+        }
+
+        let mut held: Option<T> = None;
+        while outstanding > 0 {
+            outstanding -= 1;
+            let val = match values_rx.recv() {
+                Ok(Reduction::Settled(_, Some(Ok(val)))) => val,
+                Ok(Reduction::Settled(_, Some(Err(err)))) => {
+                    tx.send(Err(err)).unwrap_or(());
+                    return;
+                }
+                Ok(Reduction::Settled(index, None)) => {
+                    return batch.died(index)
+                }
+                Ok(Reduction::Combined(Some(val))) => val,
+                Ok(Reduction::Combined(None)) | Err(_) => return
+            };
+            let first = match held.take() {
+                Some(first) => first,
+                None => {
+                    held = Some(val);
+                    continue;
+                }
+            };
+
+            outstanding += 1;
+            let func = func.clone();
+            let values_tx = values_tx.clone();
+            let slot = batch.output.clone();
+            Runtime::global().execute(Box::new(move || {
+                let combined = limit::run(|| {
+                    panic::catch_unwind(AssertUnwindSafe(|| {
+                        error::record_panic(&slot, || func(first, val))
+                    }))
+                });
+                values_tx.send(Reduction::Combined(combined.ok()))
+                    .unwrap_or(());
+            }));
+        }
+
+        if let Some(val) = held {
+            tx.send(Ok(val)).unwrap_or(());
+        }
+    }
+
+    fn impl_all_dedup(tx: Sender<Result<Vec<T>, E>>,
+                      recs: Vec<Receiver<Result<T, E>>>, batch: BatchPanic)
+    where T: Hash + Eq {
+        let mut values: Vec<Option<T>> = recs.iter().map(|_| None).collect();
+        for (index, message) in Promise::select(recs) {
+            match message {
+                Some(Ok(val)) => values[index] = Some(val),
+                Some(Err(err)) => {
+                    tx.send(Err(err)).unwrap_or(());
+                    return;
+                }
+                None => return batch.died(index)
+            }
+        }
+        let values: Vec<T> = values.into_iter().flatten().collect();
+        let first_seen: Vec<bool> = {
+            let mut seen = HashSet::new();
+            values.iter().map(|val| seen.insert(val)).collect()
+        };
+        let unique = values.into_iter().zip(first_seen)
+            .filter_map(|(val, first)| if first { Some(val) } else { None })
+            .collect();
+        tx.send(Ok(unique)).unwrap_or(());
+    }
+
+    fn impl_all_spilled(tx: Sender<Result<SpillResult<T>, E>>,
+                        recs: Vec<Receiver<Result<T, E>>>, threshold: usize,
+                        batch: BatchPanic)
+    where T: Spill, E: From<io::Error> {
+        let mut values = SpillResult::new();
+        let mut held = 0;
+        for (index, message) in Promise::select(recs) {
+            let val = match message {
+                Some(Ok(val)) => val,
+                Some(Err(err)) => {
+                    tx.send(Err(err)).unwrap_or(());
+                    return;
+                }
+                None => return batch.died(index)
+            };
+            // Once spilling starts, everything after goes to disk too, so the
+            // values stay in the order they resolved
+            let size = val.spill_size();
+            if values.spilled() == 0 && held + size <= threshold {
+                held += size;
+                values.keep(val);
+            }
+            else if let Err(err) = values.spill(&val) {
+                tx.send(Err(err.into())).unwrap_or(());
+                return;
+            }
+        }
+        tx.send(values.finish().map_err(Into::into)).unwrap_or(());
+    }
+
+    fn impl_tally(tx: Sender<Result<Tally, Infallible>>,
+                  recs: Vec<Receiver<Result<T, E>>>) {
+        let mut tally = Tally::default();
+        for rec in recs {
+            match rec.recv() {
+                Ok(Ok(_)) => tally.resolved += 1,
+                Ok(Err(_)) => tally.rejected += 1,
+                Err(_) => tally.dead += 1
+            }
+        }
+        tx.send(Ok(tally)).unwrap_or(());
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn impl_all_settled_results(
+        tx: Sender<Result<Vec<Result<T, PromiseError<E>>>, Infallible>>,
+        recs: Vec<Receiver<Result<T, E>>>) {
+        // Every result is needed, so waiting on each in turn loses nothing
+        let results = recs.into_iter().map(|rec| match rec.recv() {
+            Ok(result) => result.map_err(PromiseError::Rejected),
+            Err(_) => Err(PromiseError::Dead)
+        }).collect();
+        tx.send(Ok(results)).unwrap_or(());
+    }
+
+    fn impl_all_report(tx: Sender<Result<AllReport<T, E>, Infallible>>,
+                       recs: Vec<Receiver<Result<T, E>>>, start: Instant) {
+        let mut outcomes: Vec<Outcome<T, E>> =
+            recs.iter().map(|_| Outcome::Dead).collect();
+        let (mut resolved, mut rejected, mut dead) = (0, 0, 0);
+        for (index, message) in Promise::select(recs) {
+            outcomes[index] = match message {
+                Some(Ok(val)) => {
+                    resolved += 1;
+                    Outcome::Ok(val, start.elapsed())
+                }
+                Some(Err(err)) => {
+                    rejected += 1;
+                    Outcome::Err(err, start.elapsed())
+                }
+                None => {
+                    dead += 1;
+                    Outcome::Dead
+                }
+            };
+        }
+        let report = AllReport {
+            outcomes, resolved, rejected, dead, elapsed: start.elapsed()
+        };
+        tx.send(Ok(report)).unwrap_or(());
+    }
+
+    fn impl_all_detailed(tx: Sender<Result<Vec<T>, AllError<E>>>,
+                         recs: Vec<Receiver<Result<T, E>>>) {
+        let mut values: Vec<Option<T>> = recs.iter().map(|_| None).collect();
+        for (index, message) in Promise::select(recs) {
+            let err = match message {
+                Some(Ok(val)) => {
+                    values[index] = Some(val);
+                    continue;
+                }
+                Some(Err(err)) => AllError::Rejected(index, err),
+                None => AllError::Panicked(index)
+            };
+            tx.send(Err(err)).unwrap_or(());
+            return;
+        }
+        tx.send(Ok(values.into_iter().flatten().collect())).unwrap_or(());
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn impl_all_partial(tx: Sender<Result<Vec<T>, (E, Vec<T>)>>,
+                        recs: Vec<Receiver<Result<T, E>>>, batch: BatchPanic) {
+        let mut values: Vec<Option<T>> = recs.iter().map(|_| None).collect();
+        for (index, message) in Promise::select(recs) {
+            match message {
+                Some(Ok(val)) => values[index] = Some(val),
+                Some(Err(err)) => {
+                    let partial = values.into_iter().flatten().collect();
+                    tx.send(Err((err, partial))).unwrap_or(());
+                    return;
+                }
+                None => return batch.died(index)
+            }
+        }
+        tx.send(Ok(values.into_iter().flatten().collect())).unwrap_or(());
+    }
+
+    fn impl_pair<B>(tx: Sender<Result<(T, B), E>>,
+                    first: Receiver<Result<T, E>>,
+                    second: Receiver<Result<B, E>>, batch: BatchPanic)
+    where B: Send + 'static {
+        let joined = Arc::new(Mutex::new(Joined {
+            first: None, second: None, tx: Some(tx), batch
+        }));
+        let first_joined = joined.clone();
+        notify::when_settled(first, move |first| {
+            let message = first.recv().ok().map(Pair::First);
+            first_joined.lock().unwrap_or_else(|e| e.into_inner())
+                .settle(0, message);
+        });
+        notify::when_settled(second, move |second| {
+            let message = second.recv().ok().map(Pair::Second);
+            joined.lock().unwrap_or_else(|e| e.into_inner())
+                .settle(1, message);
+        });
+    }
+
+    fn impl_ordered_stream_terminated(tx: mpsc::Sender<StreamEvent<T, E>>,
+                                      recs: Vec<Receiver<Result<T, E>>>) {
+        // `None` until settled, then `Some(None)` if the promise died
+        let mut settled: Vec<Option<Option<Result<T, E>>>> =
+            recs.iter().map(|_| None).collect();
+        let mut next = 0;
+        let (mut completed, mut dead) = (0, 0);
+
+        for (index, message) in Promise::select(recs) {
+            match message {
+                Some(_) => completed += 1,
+                None => dead += 1
+            }
+            settled[index] = Some(message);
+
+            while next < settled.len() {
+                match settled[next].take() {
+                    Some(Some(result)) => {
+                        if tx.send(StreamEvent::Item(result)).is_err() {
+                            return;
+                        }
+                    }
+                    Some(None) => { }
+                    None => break
+                }
+                next += 1;
+            }
+        }
+        tx.send(StreamEvent::Done { completed, dead }).unwrap_or(());
+    }
+
+    fn impl_ordered_stream_progress<F>(tx: mpsc::Sender<Result<T, E>>,
+                                       recs: Vec<Receiver<Result<T, E>>>,
+                                       mut on_progress: F)
+    where F: FnMut(usize, usize) {
+        // `None` until settled, then `Some(None)` if the promise died. Held
+        // results are counted in the progress as soon as they arrive.
+        let mut settled: Vec<Option<Option<Result<T, E>>>> =
+            recs.iter().map(|_| None).collect();
+        let total = settled.len();
+        let (mut next, mut progress) = (0, 0);
+
+        for (index, message) in Promise::select(recs) {
+            progress += 1;
+            settled[index] = Some(message);
+            limit::run(|| on_progress(progress, total));
+
+            while next < total {
+                match settled[next].take() {
+                    Some(Some(result)) => {
+                        if tx.send(result).is_err() {
+                            return;
+                        }
+                    }
+                    Some(None) => { }
+                    None => break
+                }
+                next += 1;
+            }
+        }
+    }
+
+    fn impl_race_timeout(tx: Sender<Result<T, E>>,
+                         recs: Vec<Receiver<Result<T, E>>>,
+                         duration: Duration, on_timeout: E) {
+        let settled = Promise::select(recs);
+        let deadline = Instant::now() + duration;
+        loop {
+            let now = Instant::now();
+            if now >= deadline { break; }
+            match settled.recv_timeout(deadline - now) {
+                Ok((_, Some(result))) => {
+                    tx.send(result).unwrap_or(());
+                    return;
+                }
+                Ok((_, None)) => continue,
+                // Timed out, or every promise died
+                Err(_) => break
+            }
+        }
+        tx.send(Err(on_timeout)).unwrap_or(());
+    }
+
+    fn impl_race_window(tx: Sender<Result<T, E>>,
+                        recs: Vec<Receiver<Result<T, E>>>, window: Duration) {
+        let settled = Promise::select(recs);
+        let first = loop {
+            match settled.recv() {
+                Ok((_, Some(result))) => break result,
+                Ok((_, None)) => continue,
+                // All of the promises panicked
+                Err(_) => return
+            }
+        };
+
+        if first.is_err() {
+            let deadline = Instant::now() + window;
+            loop {
+                let now = Instant::now();
+                if now >= deadline { break; }
+                match settled.recv_timeout(deadline - now) {
+                    Ok((_, Some(Ok(val)))) => {
+                        tx.send(Ok(val)).unwrap_or(());
+                        return;
+                    }
+                    Ok(_) => continue,
+                    // Timed out, or every other promise has settled
+                    Err(_) => break
+                }
+            }
+        }
+        tx.send(first).unwrap_or(());
+    }
+
+    fn impl_take_n(tx: Sender<Result<Vec<T>, E>>,
+                   recs: Vec<Receiver<Result<T, E>>>,
+                   flags: Vec<Option<CancelFlag>>, n: usize) {
+        let mut values = Vec::with_capacity(n);
+        let mut pending: Vec<bool> = recs.iter().map(|_| true).collect();
+        let mut possible = recs.len();
+        let settled = Promise::select(recs);
+
+        let result = loop {
+            if values.len() == n {
+                break Some(Ok(values));
+            }
+            let (index, message) = match settled.recv() {
+                Ok(next) => next,
+                Err(_) => break None
+            };
+            pending[index] = false;
+            match message {
+                Some(Ok(val)) => values.push(val),
+                Some(Err(err)) => {
+                    possible -= 1;
+                    if possible < n { break Some(Err(err)); }
+                }
+                None => {
+                    possible -= 1;
+                    if possible < n { break None; }
+                }
+            }
+        };
+
+        for (flag, pending) in flags.iter().zip(pending) {
+            match *flag {
+                Some(ref flag) if pending => flag.cancel(),
+                _ => { }
+            }
+        }
+        if let Some(result) = result {
+            tx.send(result).unwrap_or(());
+        }
+    }
+
+    fn impl_all_deadline(tx: Sender<Result<Vec<Option<T>>, E>>,
+                         recs: Vec<Receiver<Result<T, E>>>,
+                         flags: Vec<Option<CancelFlag>>, deadline: Instant) {
+        let mut values: Vec<Option<T>> = recs.iter().map(|_| None).collect();
+        let mut pending: Vec<bool> = recs.iter().map(|_| true).collect();
+        let settled = Promise::select(recs);
+
+        let result = loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match settled.recv_timeout(timeout) {
+                Ok((index, message)) => {
+                    pending[index] = false;
+                    match message {
+                        Some(Ok(val)) => values[index] = Some(val),
+                        Some(Err(err)) => break Err(err),
+                        None => { }
+                    }
+                }
+                // The deadline passed, or every promise has settled or died
+                Err(_) => break Ok(values)
+            }
+        };
+
+        for (flag, pending) in flags.iter().zip(pending) {
+            match *flag {
+                Some(ref flag) if pending => flag.cancel(),
+                _ => { }
+            }
+        }
+        tx.send(result).unwrap_or(());
+    }
+
+    fn impl_race_outcome(tx: Sender<Result<RaceOutcome<T, E>, Infallible>>,
+                         recs: Vec<Receiver<Result<T, E>>>) {
+        let outcome = Promise::select(recs).into_iter()
+            .find_map(|(index, message)| message.map(|result| {
+                RaceOutcome::Winner { index, result }
+            }))
+            .unwrap_or(RaceOutcome::AllDead);
+        tx.send(Ok(outcome)).unwrap_or(());
+    }
+
+    fn impl_race_ok(tx: Sender<Result<T, E>>,
+                    recs: Vec<Receiver<Result<T, E>>>) {
+        let mut last_err = None;
+        for (_, message) in Promise::select(recs) {
+            match message {
+                Some(Ok(val)) => {
+                    tx.send(Ok(val)).unwrap_or(());
+                    return;
+                }
+                Some(Err(err)) => last_err = Some(err),
+                None => { }
+            }
+        }
+        if let Some(err) = last_err {
+            tx.send(Err(err)).unwrap_or(());
+        }
+    }
+
+    fn impl_any(tx: Sender<Result<T, Vec<E>>>,
+                recs: Vec<Receiver<Result<T, E>>>) {
+        let mut errors: Vec<Option<E>> = recs.iter().map(|_| None).collect();
+        for (index, message) in Promise::select(recs) {
+            match message {
+                Some(Ok(val)) => {
+                    tx.send(Ok(val)).unwrap_or(());
+                    return;
+                }
+                Some(Err(err)) => errors[index] = Some(err),
+                None => { }
+            }
+        }
+        tx.send(Err(errors.into_iter().flatten().collect())).unwrap_or(());
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn impl_race_tagged<K>(tx: Sender<Result<(K, Result<T, E>), E>>,
+                           tags: Vec<K>, recs: Vec<Receiver<Result<T, E>>>) {
+        let mut tags: Vec<Option<K>> = tags.into_iter().map(Some).collect();
+        for (index, message) in Promise::select(recs) {
+            if let (Some(result), Some(tag)) = (message, tags[index].take()) {
+                tx.send(Ok((tag, result))).unwrap_or(());
+                return;
+            }
+        }
+    }
+
+    fn impl_race_cancel(tx: Sender<Result<T, E>>,
+                        recs: Vec<Receiver<Result<T, E>>>,
+                        flags: Vec<Option<CancelFlag>>) {
+        let settled = Promise::select(recs);
+        let (winner, result) = loop {
+            match settled.recv() {
+                Ok((index, Some(result))) => break (index, result),
+                Ok((_, None)) => continue,
+                // All of the promises panicked
+                Err(_) => return
+            }
+        };
+
+        for (index, flag) in flags.iter().enumerate() {
+            match *flag {
+                Some(ref flag) if index != winner => flag.cancel(),
+                _ => { }
+            }
+        }
+        // Every loser reports once it has settled or died
+        for _ in settled { }
+
+        tx.send(result).unwrap_or(());
+    }
+
+    fn impl_race(tx: Sender<Result<T, E>>,
+                 recs: Vec<Receiver<Result<T, E>>>) {
+        // The first promise to settle takes the sender, and the race dies
+        // once the last one lets go of it. Promises which panic drop out.
+        let winner = Arc::new(Mutex::new(Some(tx)));
+        for rec in recs {
+            let winner = winner.clone();
+            notify::when_settled(rec, move |rec| {
+                if let Ok(result) = rec.recv() {
+                    let tx = winner.lock().unwrap_or_else(|e| e.into_inner())
+                        .take();
+                    if let Some(tx) = tx {
+                        tx.send(result).unwrap_or(());
+                    }
+                }
+            });
+        }
+    }
+
+    fn impl_all(tx: Sender<Result<Vec<T>, E>>,
+                recs: Vec<Receiver<Result<T, E>>>, batch: BatchPanic) {
+        match Promise::take_settled(recs) {
+            Ok((slots, indices, pending)) => {
+                Promise::impl_all_settled(tx, slots, indices, pending, batch);
+            }
+            Err(err) => tx.send(Err(err)).unwrap_or(())
+        }
+    }
+
+    fn impl_all_limited(tx: Sender<Result<Vec<T>, E>>,
+                        results: Receiver<(usize, Result<T, E>)>,
+                        count: usize) {
+        let mut slots: Vec<Option<T>> = (0..count).map(|_| None).collect();
+        for (index, result) in results.iter().take(count) {
+            match result {
+                Ok(val) => slots[index] = Some(val),
+                Err(err) => {
+                    tx.send(Err(err)).unwrap_or(());
+                    return;
+                }
+            }
+        }
+        // Only a function which died leaves a gap, and then so does the batch
+        if slots.iter().all(Option::is_some) {
+            let values = slots.into_iter().flatten().collect();
+            tx.send(Ok(values)).unwrap_or(());
+        }
+    }
+
+    /// Fills in the slots of the pending promises as they settle, where
+    /// `indices` gives the slot of each one. Nothing waits on them: the last
+    /// to settle sends the values.
+    fn impl_all_settled(tx: Sender<Result<Vec<T>, E>>,
+                        slots: Vec<Option<T>>, indices: Vec<usize>,
+                        pending: Vec<Receiver<Result<T, E>>>,
+                        batch: BatchPanic) {
+        if pending.is_empty() {
+            tx.send(Ok(slots.into_iter().flatten().collect())).unwrap_or(());
+            return;
+        }
+        let gather = Arc::new(Mutex::new(Gather {
+            slots, left: pending.len(), tx: Some(tx), batch
+        }));
+        for (rec, index) in pending.into_iter().zip(indices) {
+            let gather = gather.clone();
+            notify::when_settled(rec, move |rec| {
+                let message = rec.recv().ok();
+                gather.lock().unwrap_or_else(|e| e.into_inner())
+                    .settle(index, message);
+            });
+        }
+    }
+
+    /// Takes the values of the receivers which already have one, leaving the
+    /// rest pending along with their positions. Each value is put in its
+    /// receiver's slot, and the slots of the rest are left empty.
+    /// Disconnected receivers are left pending too, for the caller to find
+    /// dead. The first error found is returned instead.
+    #[allow(clippy::type_complexity)]
+    fn take_settled(recs: Vec<Receiver<Result<T, E>>>)
+                    -> Result<(Vec<Option<T>>, Vec<usize>,
+                               Vec<Receiver<Result<T, E>>>), E> {
+        let mut slots = Vec::with_capacity(recs.len());
+        let mut indices = Vec::new();
+        let mut pending = Vec::new();
+        for (index, rec) in recs.into_iter().enumerate() {
+            match rec.try_recv() {
+                Ok(result) => slots.push(Some(result?)),
+                Err(_) => {
+                    slots.push(None);
+                    indices.push(index);
+                    pending.push(rec);
+                }
+            }
+        }
+        Ok((slots, indices, pending))
+    }
+}
+
+impl Promise<(), ()> {
+
+    /// Removes and returns the records of every `span` which has finished
+    /// since the last call, in the order they finished.
+    pub fn take_spans() -> Vec<SpanRecord> {
+        profile::take()
+    }
+
+    /// Whether panics in promise functions unwind, and so can be caught by
+    /// `new_catch`, or abort the process.
+    pub fn panic_strategy() -> PanicStrategy {
+        if cfg!(panic = "unwind") {
+            PanicStrategy::Unwind
+        }
+        else {
+            PanicStrategy::Abort
+        }
+    }
+
+    /// Caps the number of promise functions running at once across the whole
+    /// process, for protecting a shared resource such as a database. Zero
+    /// removes the cap, which is the default.
+    ///
+    /// The cap applies to the functions given to `new` and to combinators
+    /// like `then`. Waiting on another promise does not count against it, so
+    /// long chains cannot deadlock on their own upstream promises. Functions
+    /// which are already running are not interrupted when the cap is lowered.
+    pub fn set_global_concurrency(max: usize) {
+        limit::set_max(max);
+    }
+}
+
+impl<E: Send + 'static> Promise<usize, E> {
+
+    /// Creates a stream which yields the indices `0..count`, one every
+    /// `period`, then ends.
+    ///
+    /// The timer thread exits after the last tick, or early if the stream is
+    /// dropped.
+    pub fn interval_count(period: Duration, count: usize)
+                          -> PromiseStream<usize, E> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for tick in 0..count {
+                thread::sleep(period);
+                if tx.send(Ok(tick)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        PromiseStream::from_receiver(rx)
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Promise<Promise<T, E>, E> {
+
+    /// Unwraps a promise of a promise, settling with the inner promise's
+    /// result, or the first error from either. This is `and_then` for
+    /// nesting which was built some other way.
+    pub fn flatten(self) -> Promise<T, E> {
+        self.and_then(|inner| inner)
+    }
+}
+
+impl<A, B, E> Promise<(A, B), E>
+where A: Send + 'static, B: Send + 'static, E: Send + 'static {
+
+    /// Waits for two promises of different types, resolving to both of their
+    /// values, or rejecting with the first error from either.
+    ///
+    /// The `all!` macro does the same for any number of promises.
+    pub fn join2(a: Promise<A, E>, b: Promise<B, E>) -> Promise<(A, B), E> {
+        a.__all_pair(b)
+    }
+}
+
+impl<A, B, C, E> Promise<(A, B, C), E>
+where A: Send + 'static, B: Send + 'static, C: Send + 'static,
+E: Send + 'static {
+
+    /// Like `join2`, for three promises.
+    pub fn join3(a: Promise<A, E>, b: Promise<B, E>, c: Promise<C, E>)
+                 -> Promise<(A, B, C), E> {
+        a.__all_pair(b.__all_pair(c)).map(|(a, (b, c))| (a, b, c))
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Promise<Option<T>, E> {
+
+    /// Calls a function on the value of the promise if it is fulfilled with
+    /// `Some`. A `None` value and errors are passed on untouched.
+    ///
+    /// This suits lookups which may not find anything, such as a database
+    /// query for a single row.
+    pub fn and_then_some<T2, F>(self, callback: F) -> Promise<Option<T2>, E>
+    where T2: Send + 'static, F: Send + 'static,
+    F: FnOnce(T) -> Result<T2, E> {
+        self.then_ok(move |maybe| match maybe {
+            Some(val) => callback(val).map(Some),
+            None => Ok(None)
+        })
+    }
+}
+
+/// Collecting promises gives a promise of all of their values, in the same
+/// way as `Promise::all`.
+impl<T: Send + 'static, E: Send + 'static> FromIterator<Promise<T, E>>
+for Promise<Vec<T>, E> {
+    fn from_iter<I>(promises: I) -> Promise<Vec<T>, E>
+    where I: IntoIterator<Item = Promise<T, E>> {
+        Promise::all(promises.into_iter().collect())
+    }
+}
+
+// A promise never relies on its result staying in place, so polling can
+// take it out however the promise was pinned.
+impl<T: Send, E: Send> Unpin for Promise<T, E> { }
+
+/// Awaiting a promise gives its value or error, so it can be used from any
+/// async executor.
+///
+/// A pending promise leaves the task's waker with its channel, and the
+/// promise's worker wakes it on settling or dying, so awaiting doesn't start
+/// a thread. Only a promise made by `from_receiver` has its channel relayed
+/// through another thread, once, as senders from outside the crate can't
+/// wake the task.
+///
+/// # Panics
+/// Polling panics if the promise's function died without settling, in the
+/// same way as `then_promise`. Use `wait` to handle that case instead.
+impl<T: Send + 'static, E: Send + 'static> Future for Promise<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>)
+            -> Poll<Result<T, E>> {
+        let promise = self.get_mut();
+        if let PromiseState::Resolved = promise.try_settle() {
+            if let Ok(result) = promise.take_result() {
+                return Poll::Ready(result);
+            }
+        }
+        let polled = promise.receiver().try_recv();
+        match polled {
+            Ok(result) => return Poll::Ready(result),
+            Err(TryRecvError::Disconnected) => {
+                panic!("Unable to poll promise: its function died")
+            }
+            Err(TryRecvError::Empty) => { }
+        }
+        promise.receiver().listen(cx.waker());
+        // The promise may have settled before the waker was left
+        let polled = promise.receiver().try_recv();
+        match polled {
+            Ok(result) => Poll::Ready(result),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => {
+                panic!("Unable to poll promise: its function died")
+            }
+        }
+    }
+}
+
+/// Shows whether the promise is `Pending`, `Dead`, or its result, such as
+/// `Resolved(Ok(5))`. A result which has arrived is kept in the promise, so
+/// formatting it doesn't lose it, and a `lazy` promise isn't started.
+impl<T, E> fmt::Debug for Promise<T, E>
+where T: Send + fmt::Debug + 'static, E: Send + fmt::Debug + 'static {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.state() {
+            PromiseState::Pending => f.write_str("Pending"),
+            PromiseState::Dead => f.write_str("Dead"),
+            PromiseState::Resolved => match *self.inner.borrow() {
+                Inner::Ready(ref result) => write!(f, "Resolved({:?})", result),
+                _ => unreachable!("resolved promises keep their result")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static SPAWNED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Starts a worker thread, counting it towards `threads_spawned` in tests.
+fn spawn<F: FnOnce() + Send + 'static>(func: F) -> JoinHandle<()> {
+    #[cfg(test)]
+    SPAWNED.with(|count| count.set(count.get() + 1));
+    thread::spawn(func)
+}
+
+/// Runs the body of a combinator which waits on its promises as it goes,
+/// such as one with a deadline, on the global runtime. The runtime lends out
+/// a spare worker while it waits.
+fn relay<F: FnOnce() + Send + 'static>(func: F) {
+    Runtime::global().execute(Box::new(move || runtime::blocking(func)));
+}
+
+/// Starts the thread of a combinator with a deadline, which has to pass even
+/// while every worker of the global runtime is busy.
+fn watchdog<F: FnOnce() + Send + 'static>(func: F) {
+    spawn(func);
+}
+
+/// Runs a worker's body, recording the message into `slot` if it panics.
+///
+/// The body gets a clone of `tx`, and the original is only dropped once the
+/// panic is recorded, so whoever sees the promise die also sees why.
+fn watched<S, F>(slot: &PanicSlot, tx: Sender<S>, body: F)
+where F: FnOnce(Sender<S>) {
+    error::record_panic(slot, || body(tx.clone()));
+}
+
+/// How many threads `spawn` has started from the current thread.
+#[cfg(test)]
+fn threads_spawned() -> usize {
+    SPAWNED.with(Cell::get)
+}
+
+/// What a promise settles through.
+enum Inner<T, E> {
+    /// The channel from the promise's worker.
+    Channel(Receiver<Result<T, E>>),
+    /// A result which is already known, or which arrived and was kept.
+    Ready(Result<T, E>),
+    /// The result was taken, leaving the promise dead.
+    Taken
+}
+
+impl<T, E> Inner<T, E> {
+    fn into_receiver(self) -> Receiver<Result<T, E>> {
+        match self {
+            Inner::Channel(rx) => rx,
+            Inner::Ready(result) => {
+                let (tx, rx) = channel();
+                tx.send(result).unwrap_or(());
+                rx
+            }
+            // Disconnected, as the sender is dropped straight away
+            Inner::Taken => channel().1
+        }
+    }
+}
+
+/// A message from either side of a promise pair.
+enum Pair<A, B, E> {
+    First(Result<A, E>),
+    Second(Result<B, E>)
+}
+
+/// The values gathered by `__all_pair` so far. Whichever side settles second
+/// sends both, unless the other side's error or death took the sender.
+struct Joined<A, B, E> {
+    first: Option<A>,
+    second: Option<B>,
+    tx: Option<Sender<Result<(A, B), E>>>,
+    batch: BatchPanic
+}
+
+impl<A, B, E> Joined<A, B, E> {
+    // `None` is the side at `index` dying
+    fn settle(&mut self, index: usize, message: Option<Pair<A, B, E>>) {
+        let tx = match self.tx.take() {
+            Some(tx) => tx,
+            None => return
+        };
+        match message {
+            Some(Pair::First(Ok(val))) => self.first = Some(val),
+            Some(Pair::Second(Ok(val))) => self.second = Some(val),
+            Some(Pair::First(Err(err))) | Some(Pair::Second(Err(err))) => {
+                tx.send(Err(err)).unwrap_or(());
+                return;
+            }
+            None => return self.batch.died(index)
+        }
+        match (self.first.take(), self.second.take()) {
+            (Some(a), Some(b)) => tx.send(Ok((a, b))).unwrap_or(()),
+            (first, second) => {
+                self.first = first;
+                self.second = second;
+                self.tx = Some(tx);
+            }
+        }
+    }
+}
+
+/// A value arriving during a tree reduction: from one of the input promises,
+/// by its index, or from combining two earlier values. `None` means it died.
+enum Reduction<T, E> {
+    Settled(usize, Option<Result<T, E>>),
+    Combined(Option<T>)
+}
+
+/// The panic slots of a batch of promises, and of the promise combining
+/// them, which dies along with any of them.
+struct BatchPanic {
+    inputs: Vec<Option<PanicSlot>>,
+    output: PanicSlot
+}
+
+impl BatchPanic {
+    fn of<T, E>(promises: &[Promise<T, E>], output: PanicSlot) -> BatchPanic
+    where T: Send, E: Send {
+        let inputs = promises.iter().map(|p| p.panic.clone()).collect();
+        BatchPanic { inputs, output }
+    }
+
+    /// Passes on why the promise at `index` died, before the caller drops
+    /// the combined promise's sender.
+    fn died(&self, index: usize) {
+        error::pass_panic(&self.inputs[index], &self.output);
+    }
+}
+
+/// The values gathered by `all` so far, filled in as each promise settles.
+struct Gather<T, E> {
+    slots: Vec<Option<T>>,
+    // How many promises haven't settled yet
+    left: usize,
+    // Taken once the result is sent, or the batch died
+    tx: Option<Sender<Result<Vec<T>, E>>>,
+    batch: BatchPanic
+}
+
+impl<T, E> Gather<T, E> {
+    fn settle(&mut self, index: usize, message: Option<Result<T, E>>) {
+        let tx = match self.tx.take() {
+            Some(tx) => tx,
+            None => return
+        };
+        match message {
+            Some(Ok(val)) => {
+                self.slots[index] = Some(val);
+                self.left -= 1;
+                if self.left > 0 {
+                    self.tx = Some(tx);
+                    return;
+                }
+                let values = mem::take(&mut self.slots);
+                tx.send(Ok(values.into_iter().flatten().collect()))
+                    .unwrap_or(());
+            }
+            Some(Err(err)) => tx.send(Err(err)).unwrap_or(()),
+            // The promise panicked, so there's no value for its slot
+            None => self.batch.died(index)
+        }
+    }
+}
+
+/// Calls its function when dropped, including when unwinding from a panic.
+struct Finalizer<G: FnOnce()> {
+    finalize: Option<G>
+}
+
+impl<G: FnOnce()> Drop for Finalizer<G> {
+    fn drop(&mut self) {
+        if let Some(finalize) = self.finalize.take() {
+            finalize();
         }
     }
 }
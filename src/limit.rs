@@ -0,0 +1,67 @@
+//! The process-wide cap on concurrently running promise functions.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+struct Limit {
+    // Zero means unlimited
+    max: usize,
+    running: usize
+}
+
+static LIMIT: Mutex<Limit> = Mutex::new(Limit { max: 0, running: 0 });
+static FREED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Limit> {
+    LIMIT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sets the maximum number of functions which may run at once, or removes
+/// the cap if `max` is zero.
+pub fn set_max(max: usize) {
+    lock().max = max;
+    FREED.notify_all();
+}
+
+/// Releases a slot when dropped, including when unwinding from a panic.
+struct Permit;
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        lock().running -= 1;
+        FREED.notify_all();
+    }
+}
+
+/// Takes a slot back when dropped, for the permit of a paused function.
+struct Resume;
+
+impl Drop for Resume {
+    fn drop(&mut self) {
+        acquire();
+    }
+}
+
+// Waits for a free slot, and takes it.
+fn acquire() {
+    let mut limit = lock();
+    while limit.max != 0 && limit.running >= limit.max {
+        limit = FREED.wait(limit).unwrap_or_else(|e| e.into_inner());
+    }
+    limit.running += 1;
+}
+
+/// Runs a function once there is a free slot for it.
+pub fn run<R, F: FnOnce() -> R>(func: F) -> R {
+    acquire();
+    let _permit = Permit;
+    func()
+}
+
+/// Gives up the slot of the function running `run` while it waits on `func`
+/// rather than running, such as while a cooperative task is parked. The
+/// slot is taken back before this returns, even when unwinding.
+pub fn pause<R, F: FnOnce() -> R>(func: F) -> R {
+    drop(Permit);
+    let _resume = Resume;
+    func()
+}
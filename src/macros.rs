@@ -0,0 +1,45 @@
+/// Waits for several promises with different success types, resolving to a
+/// tuple of their values in argument order.
+///
+/// Like `Promise::all`, the first promise to error rejects the combined
+/// promise straight away, without waiting for the others. All of the
+/// promises must share an error type. Between 2 and 12 promises can be
+/// given.
+///
+/// ```
+/// #[macro_use] extern crate promises;
+/// use promises::Promise;
+///
+/// # fn main() {
+/// let name: Promise<&str, ()> = Promise::resolve("answer").into_promise();
+/// let value: Promise<u32, ()> = Promise::resolve(42).into_promise();
+/// let both: Promise<(&str, u32), ()> = all!(name, value);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! all {
+    ($first:expr, $($rest:expr),+ $(,)*) => {
+        $crate::all!(@names [] [a b c d e f g h i j k l] $first, $($rest),+)
+    };
+
+    // Give each promise a name to bind its value to
+    (@names [$($named:ident $promise:expr),*] [$name:ident $($unused:ident)*]
+     $next:expr $(, $rest:expr)*) => {
+        $crate::all!(@names [$($named $promise,)* $name $next] [$($unused)*]
+                     $($rest),*)
+    };
+    (@names [$($named:ident $promise:expr),*] [$($unused:ident)*]) => {
+        $crate::all!(@nest $($promise),*)
+            .then_ok(|$crate::all!(@pattern $($named)*)| Ok(($($named),*)))
+    };
+
+    // Pair the promises up from the right: a, (b, (c, d))
+    (@nest $last:expr) => { $last };
+    (@nest $promise:expr, $($rest:expr),+) => {
+        $promise.__all_pair($crate::all!(@nest $($rest),+))
+    };
+    (@pattern $last:ident) => { $last };
+    (@pattern $name:ident $($rest:ident)+) => {
+        ($name, $crate::all!(@pattern $($rest)+))
+    };
+}
@@ -0,0 +1,227 @@
+//! Channels which wake whoever is waiting on the receiving end, so a promise
+//! can be awaited or selected over without a thread watching it.
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SendError, TryRecvError};
+use std::ops::Deref;
+use std::task::{self, Waker};
+use std::thread::Thread;
+
+use spawn;
+
+/// Where the receiving side of a channel leaves the waker to call once the
+/// channel gets a message or disconnects.
+type WakeSlot = Arc<Wake>;
+
+#[derive(Default)]
+struct Wake {
+    waker: Mutex<Option<Waker>>,
+    // How many senders are left, so only the last to go wakes on disconnect
+    senders: AtomicUsize
+}
+
+/// Creates a channel whose senders wake the receiver's waker, if it has one,
+/// after a send and when the last of them is dropped.
+pub(crate) fn channel<S>() -> (Sender<S>, Receiver<S>) {
+    let (sender, receiver) = mpsc::channel();
+    let slot = WakeSlot::default();
+    slot.senders.store(1, Ordering::Relaxed);
+    (Sender { sender: Some(sender), slot: slot.clone() },
+     Receiver { receiver, slot: Some(slot) })
+}
+
+/// The sending side of a channel from `channel`.
+pub(crate) struct Sender<S> {
+    // Only taken when the sender is dropped
+    sender: Option<mpsc::Sender<S>>,
+    slot: WakeSlot
+}
+
+/// The receiving side of a channel, which is used as an `mpsc::Receiver`.
+pub(crate) struct Receiver<S> {
+    receiver: mpsc::Receiver<S>,
+    // `None` for a channel from outside the crate, whose senders can't wake
+    slot: Option<WakeSlot>
+}
+
+impl<S> Sender<S> {
+    /// Sends `message` as `mpsc::Sender::send` does, then wakes the receiver.
+    pub(crate) fn send(&self, message: S) -> Result<(), SendError<S>> {
+        let sent = self.sender.as_ref()
+            .expect("the sender is only taken when dropped")
+            .send(message);
+        self.slot.wake();
+        sent
+    }
+}
+
+impl<S> Clone for Sender<S> {
+    fn clone(&self) -> Sender<S> {
+        self.slot.senders.fetch_add(1, Ordering::Relaxed);
+        Sender { sender: self.sender.clone(), slot: self.slot.clone() }
+    }
+}
+
+impl<S> Drop for Sender<S> {
+    fn drop(&mut self) {
+        // Drop the channel first, so a receiver woken by the last sender to
+        // go sees it disconnect
+        drop(self.sender.take());
+        if self.slot.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.slot.wake();
+        }
+    }
+}
+
+impl<S> Receiver<S> {
+    /// Wraps a channel from outside the crate. Its senders can't wake the
+    /// receiver, so `listen` relays it through a channel which can.
+    pub(crate) fn unwoken(receiver: mpsc::Receiver<S>) -> Receiver<S> {
+        Receiver { receiver, slot: None }
+    }
+
+    /// Unwraps the channel, dropping any waker left in it.
+    pub(crate) fn into_inner(self) -> mpsc::Receiver<S> {
+        self.receiver
+    }
+
+    /// Leaves `waker` to be woken once the channel gets a message or
+    /// disconnects, in place of any waker left before.
+    ///
+    /// A message may have arrived just before the waker was left, so the
+    /// caller should check the channel again afterwards.
+    pub(crate) fn listen(&mut self, waker: &Waker)
+    where S: Send + 'static {
+        if self.slot.is_none() {
+            let (tx, rx) = channel();
+            let outside = mem::replace(self, rx);
+            spawn(move || {
+                if let Ok(message) = outside.receiver.recv() {
+                    tx.send(message).unwrap_or(());
+                }
+            });
+        }
+        let slot = self.slot.as_ref().expect("the channel was just relayed");
+        let mut slot = slot.waker.lock().unwrap_or_else(|e| e.into_inner());
+        match *slot {
+            Some(ref mut left) => left.clone_from(waker),
+            None => *slot = Some(waker.clone())
+        }
+    }
+
+    /// Drops the waker left by `listen`, if it hasn't been woken yet.
+    pub(crate) fn unlisten(&self) {
+        if let Some(ref slot) = self.slot {
+            slot.waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        }
+    }
+}
+
+impl<S> Deref for Receiver<S> {
+    type Target = mpsc::Receiver<S>;
+
+    fn deref(&self) -> &mpsc::Receiver<S> {
+        &self.receiver
+    }
+}
+
+impl<S> IntoIterator for Receiver<S> {
+    type Item = S;
+    type IntoIter = mpsc::IntoIter<S>;
+
+    fn into_iter(self) -> mpsc::IntoIter<S> {
+        self.receiver.into_iter()
+    }
+}
+
+/// Calls `func` with the channel once it has a message or has disconnected,
+/// on whichever thread finds it so: this one, or the sender's as it sends.
+///
+/// This suits a promise's channel, which carries a single message: `func`
+/// is given it in a channel of its own, so it never blocks receiving it.
+pub(crate) fn when_settled<S, F>(rx: Receiver<S>, func: F)
+where S: Send + 'static, F: FnOnce(Receiver<S>) + Send + 'static {
+    let settle = Arc::new(Settle { waiting: Mutex::new(Some((rx, func))) });
+    settle.check();
+}
+
+// Holds a channel and what to do once it settles, until a wake finds it so.
+struct Settle<S, F> {
+    waiting: Mutex<Option<(Receiver<S>, F)>>
+}
+
+impl<S, F> Settle<S, F>
+where S: Send + 'static, F: FnOnce(Receiver<S>) + Send + 'static {
+    fn check(self: Arc<Self>) {
+        let mut waiting =
+            self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut rx, func) = match waiting.take() {
+            Some(waiting) => waiting,
+            // Already settled, and this is a waker left behind
+            None => return
+        };
+        let mut listening = false;
+        let settled = loop {
+            match rx.try_recv() {
+                Ok(message) => {
+                    let (tx, settled) = channel();
+                    tx.send(message).unwrap_or(());
+                    break settled;
+                }
+                Err(TryRecvError::Disconnected) => break rx,
+                // A message may have arrived before the waker was left, so
+                // the channel is checked once more after leaving it
+                Err(TryRecvError::Empty) if !listening => {
+                    rx.listen(&Waker::from(self.clone()));
+                    listening = true;
+                }
+                Err(TryRecvError::Empty) => {
+                    *waiting = Some((rx, func));
+                    return;
+                }
+            }
+        };
+        drop(waiting);
+        func(settled);
+    }
+}
+
+impl<S, F> task::Wake for Settle<S, F>
+where S: Send + 'static, F: FnOnce(Receiver<S>) + Send + 'static {
+    fn wake(self: Arc<Self>) {
+        self.check();
+    }
+}
+
+/// A waker which unparks a thread, for waiting on channels from outside of
+/// an async task.
+pub(crate) fn unparker(thread: Thread) -> Waker {
+    Waker::from(Arc::new(Unpark(thread)))
+}
+
+struct Unpark(Thread);
+
+impl task::Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl Wake {
+    // Each waker left is woken at most once, as the task polls again and
+    // leaves another if it still has to wait.
+    // The lock is let go first, as waking may leave another waker.
+    fn wake(&self) {
+        let waker =
+            self.waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Timing records for promise links run with `Promise::span`, the latency
+//! summaries of `Promise::latency_all` and `Promise::all_report`, and the
+//! queue timings of promises run on a `Runtime`.
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long one named link of a promise chain took to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanRecord {
+    /// The name given to `Promise::span`.
+    pub name: &'static str,
+    /// The time spent running the link's function. Time spent waiting for
+    /// the previous promise is not included.
+    pub duration: Duration
+}
+
+// Links run on whichever worker picks them up, so the records are kept for
+// the whole process rather than per thread.
+static SPANS: Mutex<Vec<SpanRecord>> = Mutex::new(Vec::new());
+
+/// Adds a finished span to the records.
+pub fn record(name: &'static str, duration: Duration) {
+    let mut spans = SPANS.lock().unwrap_or_else(|e| e.into_inner());
+    spans.push(SpanRecord { name, duration });
+}
+
+/// Removes and returns every span recorded so far.
+pub fn take() -> Vec<SpanRecord> {
+    let mut spans = SPANS.lock().unwrap_or_else(|e| e.into_inner());
+    mem::take(&mut *spans)
+}
+
+/// A summary of how long a batch of promises took to settle, measured from
+/// when they were passed to `Promise::latency_all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyStats {
+    /// The time taken by the fastest promise.
+    pub min: Duration,
+    /// The time taken by the slowest promise.
+    pub max: Duration,
+    /// The average time taken.
+    pub mean: Duration,
+    /// The median time taken.
+    pub p50: Duration,
+    /// The time within which 95% of the promises settled.
+    pub p95: Duration
+}
+
+impl LatencyStats {
+    /// Summarizes the times, which are all zero if there are none.
+    pub(crate) fn from_durations(mut times: Vec<Duration>) -> LatencyStats {
+        if times.is_empty() {
+            return LatencyStats::default();
+        }
+        times.sort();
+        let total: Duration = times.iter().sum();
+        // Nearest-rank percentiles
+        let rank = |percent: usize| {
+            let rank = (percent * times.len()).div_ceil(100);
+            times[rank.max(1) - 1]
+        };
+
+        LatencyStats {
+            min: times[0],
+            max: times[times.len() - 1],
+            mean: total / times.len() as u32,
+            p50: rank(50),
+            p95: rank(95)
+        }
+    }
+}
+
+/// How one promise of an `AllReport` settled, and how long it took from the
+/// start of the batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome<T, E> {
+    /// The promise resolved to the value.
+    Ok(T, Duration),
+    /// The promise rejected with the error.
+    Err(E, Duration),
+    /// The promise's function died without settling.
+    Dead
+}
+
+/// Everything about how a batch of promises settled, from
+/// `Promise::all_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllReport<T, E> {
+    /// The outcome of each promise, in input order.
+    pub outcomes: Vec<Outcome<T, E>>,
+    /// How many of the promises resolved.
+    pub resolved: usize,
+    /// How many of the promises rejected.
+    pub rejected: usize,
+    /// How many of the promises died.
+    pub dead: usize,
+    /// The time from the start of the batch until the last promise settled.
+    pub elapsed: Duration
+}
+
+/// How many of a batch of promises settled each way, from `Promise::tally`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tally {
+    /// How many of the promises resolved.
+    pub resolved: usize,
+    /// How many of the promises rejected.
+    pub rejected: usize,
+    /// How many of the promises died.
+    pub dead: usize
+}
+
+/// How long a promise's function spent waiting for a `Runtime` worker, and
+/// how long it then took to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskTiming {
+    /// The time between queueing the function and a worker starting it.
+    pub queued: Duration,
+    /// The time the function took once started, including any tasks it ran
+    /// at a `yield_point`.
+    pub executed: Duration
+}
+
+/// Where a queued task leaves its timing for its promise to read.
+pub type TimingSlot = Arc<Mutex<Option<TaskTiming>>>;
+
+/// Runs a task's function, then stores how long it waited since `queued`
+/// and how long it ran.
+pub fn timed<R, F: FnOnce() -> R>(slot: &TimingSlot, queued: Instant,
+                                  func: F) -> R {
+    let started = Instant::now();
+    let result = func();
+    let timing = TaskTiming {
+        queued: started - queued,
+        executed: started.elapsed()
+    };
+    *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(timing);
+    result
+}
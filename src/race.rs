@@ -0,0 +1,142 @@
+//! Helpers for building races between promises.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use notify::{self, channel, Sender};
+use Promise;
+
+/// Collects the competitors of a race one at a time, for when they are
+/// chosen conditionally rather than known up front.
+///
+/// Building the race has the same semantics as `Promise::race`.
+pub struct RaceBuilder<T: Send, E: Send> {
+    promises: Vec<Promise<T, E>>
+}
+
+impl<T: Send + 'static, E: Send + 'static> RaceBuilder<T, E> {
+    /// Creates a builder with no competitors yet.
+    pub fn new() -> RaceBuilder<T, E> {
+        RaceBuilder { promises: Vec::new() }
+    }
+
+    /// Adds a promise to the race.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, promise: Promise<T, E>) -> RaceBuilder<T, E> {
+        self.promises.push(promise);
+        self
+    }
+
+    /// Adds a competitor which settles to `result` after `duration`, such as
+    /// a timeout error. The timer starts when it is added, not when the race
+    /// is built.
+    pub fn add_delay(self, duration: Duration, result: Result<T, E>)
+                     -> RaceBuilder<T, E> {
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            tx.send(result).unwrap_or(());
+        });
+        self.add(Promise::from_channel(rx))
+    }
+
+    /// Races the promises added so far.
+    pub fn build(self) -> Promise<T, E> {
+        Promise::race(self.promises)
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Default for RaceBuilder<T, E> {
+    fn default() -> RaceBuilder<T, E> {
+        RaceBuilder::new()
+    }
+}
+
+/// How a race ended, as reported by `Promise::race_outcome`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RaceOutcome<T, E> {
+    /// The promise at `index` settled first, with `result`.
+    Winner {
+        /// The winner's position in the promises given to the race.
+        index: usize,
+        /// What the winner settled with.
+        result: Result<T, E>
+    },
+    /// Every promise died without settling, or there were none.
+    AllDead
+}
+
+/// A race which competitors can join while it is underway.
+///
+/// The race's promise, taken once with `result`, settles with the first
+/// competitor to settle among all of those added so far. Adding a promise
+/// after there is a winner does nothing. Cloning a group gives another
+/// handle to the same race.
+pub struct RaceGroup<T: Send, E: Send> {
+    shared: Arc<Shared<T, E>>
+}
+
+struct Shared<T: Send, E: Send> {
+    // `None` once there is a winner
+    winner: Mutex<Option<Sender<Result<T, E>>>>,
+    result: Mutex<Option<Promise<T, E>>>
+}
+
+impl<T: Send + 'static, E: Send + 'static> RaceGroup<T, E> {
+    /// Creates a race with no competitors yet.
+    pub fn new() -> RaceGroup<T, E> {
+        let (tx, rx) = channel();
+        RaceGroup {
+            shared: Arc::new(Shared {
+                winner: Mutex::new(Some(tx)),
+                result: Mutex::new(Some(Promise::from_channel(rx)))
+            })
+        }
+    }
+
+    /// Adds a promise to the race, unless there is already a winner.
+    pub fn add(&self, promise: Promise<T, E>) {
+        if self.shared.winner().is_none() {
+            return;
+        }
+        let recv = promise.into_channel();
+        let shared = self.shared.clone();
+        notify::when_settled(recv, move |recv| {
+            // Promises which panic drop out of the race
+            if let Ok(result) = recv.recv() {
+                if let Some(winner) = shared.winner().take() {
+                    winner.send(result).unwrap_or(());
+                }
+            }
+        });
+    }
+
+    /// Takes the promise for the winner's result.
+    ///
+    /// # Panics
+    /// Panics if the result was already taken through any handle.
+    pub fn result(&self) -> Promise<T, E> {
+        let mut result =
+            self.shared.result.lock().unwrap_or_else(|e| e.into_inner());
+        result.take().expect("The RaceGroup's result was already taken")
+    }
+}
+
+impl<T: Send, E: Send> Shared<T, E> {
+    fn winner(&self) -> MutexGuard<'_, Option<Sender<Result<T, E>>>> {
+        self.winner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Send, E: Send> Clone for RaceGroup<T, E> {
+    fn clone(&self) -> RaceGroup<T, E> {
+        RaceGroup { shared: self.shared.clone() }
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Default for RaceGroup<T, E> {
+    fn default() -> RaceGroup<T, E> {
+        RaceGroup::new()
+    }
+}
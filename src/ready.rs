@@ -0,0 +1,59 @@
+//! Promises which are already settled when they are created.
+
+use Promise;
+
+/// A promise whose result is known up front.
+///
+/// A `ReadyPromise` is just its `Result`: it never has a thread or channel,
+/// and its combinators run immediately on the calling thread. It can be
+/// turned into a regular `Promise` with `into_promise` when a value needs to
+/// join asynchronous code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadyPromise<T, E> {
+    result: Result<T, E>
+}
+
+impl<T, E> ReadyPromise<T, E> {
+    /// Creates a ready promise with the result value.
+    pub const fn from_result(result: Result<T, E>) -> ReadyPromise<T, E> {
+        ReadyPromise { result }
+    }
+
+    /// Creates a ready promise which resolves to a value.
+    pub const fn resolve(val: T) -> ReadyPromise<T, E> {
+        ReadyPromise::from_result(Ok(val))
+    }
+
+    /// Creates a ready promise which resolves to an error.
+    pub const fn reject(err: E) -> ReadyPromise<T, E> {
+        ReadyPromise::from_result(Err(err))
+    }
+
+    /// Transforms the value, if there is one, on the calling thread.
+    pub fn map<T2, F>(self, func: F) -> ReadyPromise<T2, E>
+    where F: FnOnce(T) -> T2 {
+        ReadyPromise::from_result(self.result.map(func))
+    }
+
+    /// Chains another ready promise onto the value, if there is one, on the
+    /// calling thread.
+    pub fn and_then<T2, F>(self, func: F) -> ReadyPromise<T2, E>
+    where F: FnOnce(T) -> ReadyPromise<T2, E> {
+        match self.result {
+            Ok(val) => func(val),
+            Err(err) => ReadyPromise::reject(err)
+        }
+    }
+
+    /// Returns the result without waiting.
+    pub fn into_result(self) -> Result<T, E> {
+        self.result
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> ReadyPromise<T, E> {
+    /// Converts this into a regular `Promise` with the same result.
+    pub fn into_promise(self) -> Promise<T, E> {
+        Promise::from_result(self.result)
+    }
+}
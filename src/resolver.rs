@@ -0,0 +1,33 @@
+//! Settling a promise from outside of a worker.
+
+use notify::Sender;
+
+/// Settles the promise it was created with, by `Promise::channel`.
+///
+/// A resolver can be moved anywhere, such as into a callback fired by
+/// another library. Dropping it without settling kills the promise.
+pub struct Resolver<T: Send, E: Send> {
+    sender: Sender<Result<T, E>>
+}
+
+impl<T: Send, E: Send> Resolver<T, E> {
+    pub(crate) fn new(sender: Sender<Result<T, E>>) -> Resolver<T, E> {
+        Resolver { sender }
+    }
+
+    /// Fulfills the promise with `val`.
+    pub fn resolve(self, val: T) {
+        self.settle(Ok(val));
+    }
+
+    /// Rejects the promise with `err`.
+    pub fn reject(self, err: E) {
+        self.settle(Err(err));
+    }
+
+    /// Settles the promise with `result`.
+    pub fn settle(self, result: Result<T, E>) {
+        // Nobody is waiting if the promise was dropped
+        self.sender.send(result).unwrap_or(());
+    }
+}
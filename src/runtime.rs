@@ -0,0 +1,547 @@
+//! A fixed set of worker threads which promise functions can be queued on,
+//! instead of each getting a thread of its own.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use limit;
+use notify;
+use Promise;
+use PromiseError;
+use spawn;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// A pool of worker threads running queued tasks in order.
+///
+/// While one of its tasks waits on a promise, the runtime lends out a spare
+/// worker for the tasks queued behind it, so waiting on work queued on the
+/// same runtime doesn't deadlock it. Spares don't outlast the waits they
+/// stand in for, so no more tasks run at once than there are workers.
+///
+/// Runtimes are handles: cloning one refers to the same workers. The
+/// workers exit once every handle has been dropped and the queue is empty.
+#[derive(Clone)]
+pub struct Runtime {
+    handle: Arc<Handle>
+}
+
+// Shuts the workers down when the last `Runtime` goes away. The workers
+// themselves only hold `Shared`, so they don't keep the runtime alive.
+struct Handle {
+    shared: Arc<Shared>
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    // Signalled as workers take tasks, for callers waiting on a full queue
+    room: Condvar,
+    // Whether spare workers stand in for blocked ones
+    lends: bool
+}
+
+struct Queue {
+    tasks: VecDeque<Task>,
+    // Tasks which may only run on one worker, indexed by worker
+    pinned: Vec<VecDeque<Task>>,
+    // How many tasks, pinned or not, may wait at once
+    capacity: usize,
+    // Workers waiting for a task, spares included
+    idle: usize,
+    // Tasks waiting inside `blocking`, and the spares standing in for them
+    blocked: usize,
+    spares: usize,
+    shutdown: bool
+}
+
+/// Which runtime a promise's function was queued on, kept by the promise
+/// without keeping the runtime's workers alive.
+#[derive(Clone)]
+pub(crate) struct Owner(Weak<Shared>);
+
+/// Identifies one of a `Runtime`'s worker threads, for pinning tasks to it
+/// with `Runtime::execute_on` or `Promise::new_pinned`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorkerId(usize);
+
+/// Somewhere tasks can be run, such as a `Runtime`. Given to
+/// `Promise::then_on` to choose where a link of a chain runs.
+pub trait Executor {
+    /// Arranges for `task` to be run.
+    fn spawn(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+static INSTALLED: Mutex<Option<Runtime>> = Mutex::new(None);
+
+static DEFAULT: OnceLock<Runtime> = OnceLock::new();
+
+// How many tasks the default runtime queues per worker before `execute`
+// waits for room.
+const DEFAULT_CAPACITY: usize = 256;
+
+thread_local! {
+    // The runtime whose tasks this thread runs, if any, by address
+    static WORKING_FOR: Cell<usize> = const { Cell::new(0) };
+    // The runtime which lends a spare when this worker blocks, taken while
+    // it is blocked so nested waits only count once
+    static LENDING: RefCell<Option<Arc<Shared>>> =
+        const { RefCell::new(None) };
+}
+
+impl Runtime {
+    /// Starts a runtime with the given number of worker threads, and a queue
+    /// which can grow as long as it needs to.
+    ///
+    /// # Panics
+    /// Panics if `workers` is zero.
+    pub fn new(workers: usize) -> Runtime {
+        Runtime::with_capacity(workers, usize::MAX)
+    }
+
+    /// Starts a runtime with the given number of worker threads, whose queue
+    /// holds at most `capacity` waiting tasks.
+    ///
+    /// Once the queue is full, `execute` and the constructors queueing on
+    /// the runtime block until a worker takes a task, slowing producers down
+    /// to the pace of the workers. Tasks queued by the runtime's own tasks
+    /// don't wait, as they could be waiting on themselves.
+    ///
+    /// # Panics
+    /// Panics if `workers` or `capacity` is zero.
+    pub fn with_capacity(workers: usize, capacity: usize) -> Runtime {
+        Runtime::start(workers, capacity, true)
+    }
+
+    fn start(workers: usize, capacity: usize, lends: bool) -> Runtime {
+        assert!(workers > 0, "a runtime needs at least one worker");
+        assert!(capacity > 0, "a runtime needs room for at least one task");
+        let queue = Queue {
+            tasks: VecDeque::new(),
+            pinned: (0..workers).map(|_| VecDeque::new()).collect(),
+            capacity,
+            idle: 0,
+            blocked: 0,
+            spares: 0,
+            shutdown: false
+        };
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(queue),
+            ready: Condvar::new(),
+            room: Condvar::new(),
+            lends
+        });
+
+        for index in 0..workers {
+            let shared = shared.clone();
+            thread::spawn(move || shared.work(index));
+        }
+
+        Runtime { handle: Arc::new(Handle { shared }) }
+    }
+
+    /// Makes this the process's global runtime, replacing any previously
+    /// installed one. Promise constructors which support a runtime, such
+    /// as `Promise::new_cooperative`, queue their work on it.
+    pub fn install(&self) {
+        *installed() = Some(self.clone());
+    }
+
+    /// Removes the global runtime, if there is one.
+    pub fn uninstall() {
+        *installed() = None;
+    }
+
+    /// Marks a promise as queued on this runtime.
+    pub(crate) fn owner(&self) -> Owner {
+        Owner(Arc::downgrade(&self.handle.shared))
+    }
+
+    fn owns(&self, owner: &Owner) -> bool {
+        Weak::ptr_eq(&owner.0, &Arc::downgrade(&self.handle.shared))
+    }
+
+    /// Returns a handle to the global runtime, if one is installed.
+    pub fn installed() -> Option<Runtime> {
+        installed().clone()
+    }
+
+    /// Returns a handle to the global runtime if one is installed, or else to
+    /// a default runtime with a worker for each CPU. The default runtime is
+    /// started the first time it is needed and runs for the rest of the
+    /// process. Its queue holds 256 tasks per worker.
+    pub fn global() -> Runtime {
+        if let Some(runtime) = Runtime::installed() {
+            return runtime;
+        }
+        DEFAULT.get_or_init(|| {
+            let workers = thread::available_parallelism()
+                .map(|count| count.get())
+                .unwrap_or(1);
+            Runtime::with_capacity(workers, workers * DEFAULT_CAPACITY)
+        }).clone()
+    }
+
+    /// Queues a task to be run by the next free worker, first waiting for
+    /// room if the queue is full.
+    pub fn execute(&self, task: Box<dyn FnOnce() + Send>) {
+        self.handle.shared.push(task);
+    }
+
+    /// Queues `task` once `rx` has a message or has disconnected, so the
+    /// task can receive from it without holding up a worker.
+    ///
+    /// This suits a promise's channel, which carries a single message: the
+    /// task is given it in a channel of its own.
+    pub(crate) fn execute_after<S, F>(&self, rx: notify::Receiver<S>,
+                                      task: F)
+    where S: Send + 'static, F: FnOnce(notify::Receiver<S>) + Send + 'static {
+        let runtime = self.clone();
+        notify::when_settled(rx, move |rx| {
+            runtime.execute(Box::new(move || task(rx)));
+        });
+    }
+
+    /// The IDs of this runtime's workers.
+    pub fn workers(&self) -> Vec<WorkerId> {
+        (0..self.handle.shared.lock().pinned.len()).map(WorkerId).collect()
+    }
+
+    /// Queues a task to be run by one particular worker, after the other
+    /// tasks pinned to it. Tasks sharing thread-local state can be pinned
+    /// to the same worker to always run on the same thread. Pinned tasks
+    /// count towards the queue's capacity like any other.
+    ///
+    /// # Panics
+    /// Panics if `worker` doesn't belong to this runtime.
+    pub fn execute_on(&self, worker: WorkerId,
+                      task: Box<dyn FnOnce() + Send>) {
+        self.handle.shared.push_pinned(worker.0, task);
+    }
+
+    /// Queues a task which can pause at its `Cooperator`'s yield points.
+    ///
+    /// The task gets a thread of its own, so that it can be parked, but it
+    /// only runs while a worker drives it: the worker waits until the task
+    /// yields or finishes. The runtime still runs one task per worker at a
+    /// time.
+    pub(crate) fn execute_cooperative<F>(&self, task: F)
+    where F: FnOnce(&Cooperator) + Send + 'static {
+        let shared = self.handle.shared.clone();
+        self.execute(Box::new(move || {
+            let (yielded_tx, yielded_rx) = channel();
+            let (resume_tx, resume_rx) = channel();
+            let cooperator = Cooperator {
+                parking: Some(Parking {
+                    shared: shared.clone(),
+                    yielded: yielded_tx,
+                    resume: resume_rx
+                })
+            };
+            let runtime = shared.address();
+            spawn(move || {
+                WORKING_FOR.with(|working| working.set(runtime));
+                task(&cooperator)
+            });
+            drive(shared, resume_tx, yielded_rx);
+        }));
+    }
+}
+
+fn installed() -> MutexGuard<'static, Option<Runtime>> {
+    INSTALLED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.ready.notify_all();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn address(&self) -> usize {
+        self as *const Shared as usize
+    }
+
+    // Locks the queue once it has room for another task.
+    fn lock_room(&self) -> MutexGuard<'_, Queue> {
+        let mut queue = self.lock();
+        // The runtime's own tasks would be waiting on themselves
+        if WORKING_FOR.with(Cell::get) == self.address() {
+            return queue;
+        }
+        while queue.len() >= queue.capacity {
+            queue = self.room.wait(queue).unwrap_or_else(|e| e.into_inner());
+        }
+        queue
+    }
+
+    fn push(self: &Arc<Self>, task: Task) {
+        {
+            let mut queue = self.lock_room();
+            queue.tasks.push_back(task);
+            self.lend(&mut queue);
+        }
+        self.ready.notify_one();
+    }
+
+    // Starts a spare if a task is waiting, every worker is busy, and some of
+    // them are only blocked.
+    fn lend(self: &Arc<Self>, queue: &mut Queue) {
+        if self.lends && queue.idle == 0 && queue.spares < queue.blocked
+            && !queue.tasks.is_empty() {
+            queue.spares += 1;
+            let shared = self.clone();
+            thread::spawn(move || shared.stand_in());
+        }
+    }
+
+    fn push_pinned(&self, worker: usize, task: Task) {
+        {
+            let mut queue = self.lock_room();
+            assert!(worker < queue.pinned.len(),
+                    "worker {} doesn't belong to this runtime", worker);
+            queue.pinned[worker].push_back(task);
+        }
+        // Only the one worker can take it, and it may not be the one woken
+        self.ready.notify_all();
+    }
+
+    fn work(self: Arc<Self>, index: usize) {
+        WORKING_FOR.with(|working| working.set(self.address()));
+        if self.lends {
+            LENDING.with(|lending| *lending.borrow_mut() = Some(self.clone()));
+        }
+        loop {
+            let task = {
+                let mut queue = self.lock();
+                loop {
+                    if let Some(task) = queue.pinned[index].pop_front() {
+                        break task;
+                    }
+                    if let Some(task) = queue.tasks.pop_front() {
+                        break task;
+                    }
+                    if queue.shutdown { return; }
+                    queue.idle += 1;
+                    queue = self.ready.wait(queue)
+                        .unwrap_or_else(|e| e.into_inner());
+                    queue.idle -= 1;
+                }
+            };
+            self.room.notify_one();
+            run(task);
+        }
+    }
+
+    // A spare takes unpinned tasks until there are more spares than blocked
+    // workers, or the runtime shuts down.
+    fn stand_in(self: Arc<Self>) {
+        WORKING_FOR.with(|working| working.set(self.address()));
+        LENDING.with(|lending| *lending.borrow_mut() = Some(self.clone()));
+        loop {
+            let task = {
+                let mut queue = self.lock();
+                loop {
+                    if queue.spares > queue.blocked
+                        || queue.shutdown && queue.tasks.is_empty() {
+                        queue.spares -= 1;
+                        return;
+                    }
+                    if let Some(task) = queue.tasks.pop_front() {
+                        break task;
+                    }
+                    queue.idle += 1;
+                    queue = self.ready.wait(queue)
+                        .unwrap_or_else(|e| e.into_inner());
+                    queue.idle -= 1;
+                }
+            };
+            self.room.notify_one();
+            run(task);
+        }
+    }
+}
+
+/// Runs `func`, which may block waiting on other tasks. Called from a worker
+/// of a runtime, a spare takes over the worker's queue meanwhile if it's
+/// needed; anywhere else this just calls `func`.
+pub(crate) fn blocking<R, F: FnOnce() -> R>(func: F) -> R {
+    let shared = match LENDING.with(|lending| lending.borrow_mut().take()) {
+        Some(shared) => shared,
+        None => return func()
+    };
+    {
+        let mut queue = shared.lock();
+        queue.blocked += 1;
+        shared.lend(&mut queue);
+    }
+    let _unblocked = Unblocked(Some(shared));
+    func()
+}
+
+// Ends a `blocking` call, even one which unwinds.
+struct Unblocked(Option<Arc<Shared>>);
+
+impl Drop for Unblocked {
+    fn drop(&mut self) {
+        let shared = self.0.take().expect("only taken when dropped");
+        shared.lock().blocked -= 1;
+        // Spares no longer needed go, and idle ones have to wake to see it
+        shared.ready.notify_all();
+        LENDING.with(|lending| *lending.borrow_mut() = Some(shared));
+    }
+}
+
+impl Queue {
+    // The tasks waiting, pinned or not.
+    fn len(&self) -> usize {
+        self.tasks.len() + self.pinned.iter().map(VecDeque::len).sum::<usize>()
+    }
+}
+
+// A panicking task only kills its own promise, not the worker running it.
+fn run(task: Task) {
+    panic::catch_unwind(AssertUnwindSafe(task)).unwrap_or(());
+}
+
+// Holds the worker until the cooperative task yields or finishes. A task
+// which yielded is queued again at the back, and the worker which reaches
+// it wakes it up and drives it in turn.
+fn drive(shared: Arc<Shared>, resume: Sender<()>, yielded: Receiver<()>) {
+    if yielded.recv().is_ok() {
+        let requeued = shared.clone();
+        shared.push(Box::new(move || {
+            resume.send(()).unwrap_or(());
+            drive(requeued, resume, yielded);
+        }));
+    }
+}
+
+/// An executor with a single thread of its own, separate from the global
+/// runtime.
+///
+/// Every task spawned on a pool runs on the same dedicated thread, one at a
+/// time in the order spawned, so tasks can share thread-confined state such
+/// as a thread local. Unlike the global runtime, a pool never lends a
+/// spare worker while a task waits, so a task blocked on another of the
+/// pool's promises never sees it run. The thread exits once the pool is
+/// dropped and its queued tasks have run.
+#[derive(Clone)]
+pub struct LocalPool {
+    runtime: Runtime
+}
+
+impl LocalPool {
+    /// Starts a pool and its thread.
+    pub fn new() -> LocalPool {
+        LocalPool { runtime: Runtime::start(1, usize::MAX, false) }
+    }
+
+    /// Creates a promise whose function is queued on the pool's thread, as
+    /// with `Promise::new_on`.
+    pub fn spawn<T, E, F>(&self, func: F) -> Promise<T, E>
+    where T: Send + 'static, E: Send + 'static,
+    F: FnOnce() -> Result<T, E>, F: Send + 'static {
+        Promise::new_on(&self.runtime, func)
+    }
+
+    /// Blocks the current thread until `promise`, spawned on this pool,
+    /// settles, returning its value or error as with `Promise::wait`.
+    ///
+    /// Nothing is run on the calling thread: the pool keeps running its
+    /// tasks, this promise's included, on its own thread in the meantime. A
+    /// promise whose function panicked gives `PromiseError::Panicked`.
+    ///
+    /// # Panics
+    /// Panics if the promise wasn't spawned on this pool, or if called from
+    /// one of the pool's own tasks, which would wait on itself.
+    pub fn run_until<T, E>(&self, promise: Promise<T, E>)
+                           -> Result<T, PromiseError<E>>
+    where T: Send + 'static, E: Send + 'static {
+        assert!(promise.runtime.as_ref()
+                    .is_some_and(|owner| self.runtime.owns(owner)),
+                "run_until was given a promise from outside the pool");
+        assert!(WORKING_FOR.with(Cell::get)
+                    != self.runtime.handle.shared.address(),
+                "run_until was called from the pool's own thread");
+        promise.wait()
+    }
+}
+
+/// Queues tasks with `execute`. `Runtime::global()` is the executor to use
+/// when there's no particular pool in mind.
+impl Executor for Runtime {
+    fn spawn(&self, task: Box<dyn FnOnce() + Send>) {
+        self.execute(task);
+    }
+}
+
+impl Executor for LocalPool {
+    fn spawn(&self, task: Box<dyn FnOnce() + Send>) {
+        self.runtime.execute(task);
+    }
+}
+
+impl Default for LocalPool {
+    fn default() -> LocalPool {
+        LocalPool::new()
+    }
+}
+
+/// Given to the functions of `Promise::new_cooperative`, to let a long
+/// running task make way for others.
+pub struct Cooperator {
+    parking: Option<Parking>
+}
+
+// How a cooperative task hands its worker back, and waits to be driven again.
+// The task runs inside `limit::run`, so it holds a slot of the global cap.
+struct Parking {
+    shared: Arc<Shared>,
+    yielded: Sender<()>,
+    resume: Receiver<()>
+}
+
+impl Cooperator {
+    /// A `Cooperator` for a task with a thread of its own, which has no one
+    /// to yield to.
+    pub(crate) fn detached() -> Cooperator {
+        Cooperator { parking: None }
+    }
+
+    /// Parks the current task at the back of the runtime's queue, handing
+    /// its worker to the tasks waiting in front of it. The task carries on
+    /// once a worker reaches it again.
+    ///
+    /// The task keeps its own thread while parked, so its stack doesn't grow
+    /// however often it yields, and the other tasks run on the workers as
+    /// usual. A task which waits on the one that yielded to it holds its
+    /// worker while it does, so with a single worker that still deadlocks.
+    /// Without a runtime, or when no tasks are waiting, this does nothing.
+    pub fn yield_point(&self) {
+        let parking = match self.parking {
+            Some(ref parking) => parking,
+            None => return
+        };
+        // The task would only be picked straight back up. Tasks pinned to a
+        // worker count too, as they are waiting just the same.
+        if parking.shared.lock().len() == 0 {
+            return;
+        }
+        // A parked task isn't running, so it makes way under the global cap
+        limit::pause(|| {
+            parking.yielded.send(()).unwrap_or(());
+            parking.resume.recv().unwrap_or(());
+        });
+    }
+}
@@ -0,0 +1,82 @@
+//! Promises whose result can be handed to any number of subscribers.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use error::PanicSlot;
+use notify::{channel, Sender};
+use Promise;
+use Runtime;
+
+/// A promise whose result is kept and copied out to every subscriber, for a
+/// value with several downstream consumers. Created by `Promise::shared`.
+///
+/// Each call to `subscribe` gives a regular promise of its own, which can be
+/// chained as usual. Subscribing after the result has arrived gives an
+/// already settled promise. Cloning a shared promise gives another handle to
+/// the same result.
+pub struct SharedPromise<T: Send, E: Send> {
+    state: Arc<Mutex<State<T, E>>>,
+    // The shared promise's panic slot, which every subscriber reports from
+    panic: PanicSlot
+}
+
+enum State<T, E> {
+    // The senders of the subscribers waiting for the result
+    Pending(Vec<Sender<Result<T, E>>>),
+    Settled(Result<T, E>),
+    Dead
+}
+
+impl<T, E> SharedPromise<T, E>
+where T: Clone + Send + 'static, E: Clone + Send + 'static {
+    pub(crate) fn new(promise: Promise<T, E>) -> SharedPromise<T, E> {
+        let state = Arc::new(Mutex::new(State::Pending(Vec::new())));
+        let panic = promise.panic_slot();
+        let recv = promise.into_channel();
+        let relay = state.clone();
+
+        Runtime::global().execute_after(recv, move |recv| {
+            let settled = match recv.recv() {
+                Ok(result) => State::Settled(result),
+                Err(_) => State::Dead
+            };
+            let mut state = lock(&relay);
+            if let State::Pending(ref subscribers) = *state {
+                if let State::Settled(ref result) = settled {
+                    for subscriber in subscribers {
+                        subscriber.send(result.clone()).unwrap_or(());
+                    }
+                }
+            }
+            // Dropping the senders kills the subscribers of a dead promise
+            *state = settled;
+        });
+
+        SharedPromise { state, panic }
+    }
+
+    /// Creates a promise which settles with a copy of the shared result, or
+    /// dies of the same panic if the shared promise died.
+    pub fn subscribe(&self) -> Promise<T, E> {
+        let mut state = lock(&self.state);
+        match *state {
+            State::Pending(ref mut subscribers) => {
+                let (tx, rx) = channel();
+                subscribers.push(tx);
+                Promise::with_panic(rx, self.panic.clone())
+            }
+            State::Settled(ref result) => Promise::from_result(result.clone()),
+            State::Dead => Promise::with_panic(channel().1, self.panic.clone())
+        }
+    }
+}
+
+fn lock<T, E>(state: &Mutex<State<T, E>>) -> MutexGuard<'_, State<T, E>> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<T: Send, E: Send> Clone for SharedPromise<T, E> {
+    fn clone(&self) -> SharedPromise<T, E> {
+        SharedPromise { state: self.state.clone(), panic: self.panic.clone() }
+    }
+}
@@ -0,0 +1,174 @@
+//! Spilling the results of a large batch to disk, for `Promise::all_spilled`.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec;
+
+/// A value which can be written out of memory and read back, so a batch of
+/// them can be spilled to disk by `Promise::all_spilled`.
+pub trait Spill: Sized {
+    /// Roughly how many bytes the value takes up in memory. Defaults to the
+    /// size of the type itself, which leaves out anything on the heap.
+    fn spill_size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+
+    /// Encodes the value.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Decodes a value encoded by `to_bytes`.
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self>;
+}
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// The values of a batch, some of which may have been spilled to a
+/// temporary file. Iterating yields them in the order they were collected,
+/// reading the spilled ones back as it goes.
+///
+/// The temporary file is removed when the result or its iterator is
+/// dropped.
+#[derive(Debug)]
+pub struct SpillResult<T> {
+    memory: Vec<T>,
+    spill: Option<(TempFile, BufWriter<File>)>,
+    spilled: usize
+}
+
+/// Iterates over the values of a `SpillResult`.
+#[derive(Debug)]
+pub struct SpillIter<T> {
+    memory: vec::IntoIter<T>,
+    reader: Option<BufReader<File>>,
+    // Reported in place of the spilled values if the file can't be read
+    error: Option<io::Error>,
+    remaining: usize,
+    _file: Option<TempFile>
+}
+
+// Removes the file at the path when dropped.
+#[derive(Debug)]
+struct TempFile {
+    path: PathBuf
+}
+
+impl<T: Spill> SpillResult<T> {
+    pub(crate) fn new() -> SpillResult<T> {
+        SpillResult { memory: Vec::new(), spill: None, spilled: 0 }
+    }
+
+    /// How many of the values are kept in memory.
+    pub fn in_memory(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// How many of the values were spilled to disk.
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    pub(crate) fn keep(&mut self, val: T) {
+        self.memory.push(val);
+    }
+
+    pub(crate) fn spill(&mut self, val: &T) -> io::Result<()> {
+        if self.spill.is_none() {
+            self.spill = Some(TempFile::create()?);
+        }
+        if let Some((_, ref mut writer)) = self.spill {
+            let bytes = val.to_bytes();
+            writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+        self.spilled += 1;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<SpillResult<T>> {
+        if let Some((_, ref mut writer)) = self.spill {
+            writer.flush()?;
+        }
+        Ok(self)
+    }
+}
+
+impl<T: Spill> IntoIterator for SpillResult<T> {
+    type Item = io::Result<T>;
+    type IntoIter = SpillIter<T>;
+
+    fn into_iter(self) -> SpillIter<T> {
+        let (file, reader) = match self.spill {
+            Some((file, writer)) => (Some(file), Some(rewind(writer))),
+            None => (None, None)
+        };
+        let (reader, error) = match reader {
+            Some(Ok(reader)) => (Some(reader), None),
+            Some(Err(err)) => (None, Some(err)),
+            None => (None, None)
+        };
+        SpillIter {
+            memory: self.memory.into_iter(),
+            reader,
+            error,
+            remaining: self.spilled,
+            _file: file
+        }
+    }
+}
+
+impl<T: Spill> Iterator for SpillIter<T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<io::Result<T>> {
+        if let Some(val) = self.memory.next() {
+            return Some(Ok(val));
+        }
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if let Some(err) = self.error.take() {
+            self.remaining = 0;
+            return Some(Err(err));
+        }
+        self.reader.as_mut().map(read_record)
+    }
+}
+
+// Turns the spill file's writer into a reader from the start of the file.
+fn rewind(writer: BufWriter<File>) -> io::Result<BufReader<File>> {
+    let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(BufReader::new(file))
+}
+
+fn read_record<T: Spill>(reader: &mut BufReader<File>) -> io::Result<T> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    T::from_bytes(&bytes)
+}
+
+impl TempFile {
+    fn create() -> io::Result<(TempFile, BufWriter<File>)> {
+        let id = SPILL_FILES.fetch_add(1, Ordering::SeqCst);
+        let name = format!("promises-spill-{}-{}", process::id(), id);
+        let path = env::temp_dir().join(name);
+        let file = OpenOptions::new()
+            .read(true).write(true).create_new(true)
+            .open(&path)?;
+        Ok((TempFile { path }, BufWriter::new(file)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).unwrap_or(());
+    }
+}
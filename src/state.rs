@@ -0,0 +1,12 @@
+//! Reporting where a promise is in its life, for `Promise::state`.
+
+/// Whether a promise has settled yet, as reported by `Promise::state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromiseState {
+    /// The promise's function is still running.
+    Pending,
+    /// The promise has settled, with either a value or an error.
+    Resolved,
+    /// The promise's function died without settling.
+    Dead
+}
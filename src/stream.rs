@@ -0,0 +1,55 @@
+//! Streams of results, for work which produces more than one value over time.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use spawn;
+
+/// A sequence of results arriving from a background thread.
+///
+/// Where a `Promise` settles exactly once, a stream yields any number of
+/// results and then ends. Iterating blocks until the next result arrives,
+/// and finishes once the producing thread has stopped.
+pub struct PromiseStream<T: Send, E: Send> {
+    receiver: Receiver<Result<T, E>>
+}
+
+impl<T: Send, E: Send> PromiseStream<T, E> {
+    /// Creates a stream of the results sent into a channel. The stream ends
+    /// once every sender has been dropped.
+    pub fn from_receiver(rx: Receiver<Result<T, E>>) -> PromiseStream<T, E> {
+        PromiseStream { receiver: rx }
+    }
+
+    /// Creates a stream of the results `func` sends, such as the lines of a
+    /// file as they are read. The function runs on a thread of its own, and
+    /// the stream ends once it returns and drops the sender.
+    pub fn generator<F>(func: F) -> PromiseStream<T, E>
+    where T: 'static, E: 'static,
+    F: FnOnce(Sender<Result<T, E>>), F: Send + 'static {
+        let (tx, rx) = channel();
+        spawn(move || func(tx));
+        PromiseStream::from_receiver(rx)
+    }
+}
+
+impl<T: Send, E: Send> Iterator for PromiseStream<T, E> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Result<T, E>> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// An event of a stream which reports how it ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent<T, E> {
+    /// The next result of the stream.
+    Item(Result<T, E>),
+    /// Sent once, after the last item.
+    Done {
+        /// The number of producers which sent a result.
+        completed: usize,
+        /// The number of producers which died without a result.
+        dead: usize
+    }
+}
@@ -3,13 +3,74 @@
 // These tests involve async files
 use std::fs;
 use std::fs::File;
+use std::future::Future;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io;
 use std::io::prelude::*;
-use std::result::Result;
+use std::num::ParseIntError;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+use std::slice;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
 
-use super::Promise;
+use super::threads_spawned;
+use super::{Accumulator, AllCollector, AllError, AllReport, Cancelled,
+            Coalescer, DeadlineExceeded, Executor, LocalPool, Outcome,
+            PanicMessage, PanicStrategy, Promise, PromiseError, PromiseScope,
+            PromiseState, PromiseStream, RaceBuilder, RaceGroup, RaceOutcome,
+            ReadyPromise, Resolver, Runtime, SharedPromise, Spill, StreamEvent,
+            Tally, TerminatedOr, TimeoutError};
+
+// Blocks the test until the promise settles and hands back its result.
+fn settle<T, E>(promise: Promise<T, E>) -> Result<T, E>
+where T: Send + 'static, E: Send + 'static {
+    let (tx, rx) = mpsc::channel();
+    promise.then_result(move |result| {
+        tx.send(result).unwrap();
+        Ok::<(), ()>(())
+    });
+    rx.recv().unwrap()
+}
+
+// Wakes a task by counting the wakeup and unparking the polling thread.
+struct ThreadWaker {
+    thread: thread::Thread,
+    wakes: AtomicUsize
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+// A minimal executor: polls the future on this thread, parking in between.
+// Gives back the output along with how many times the future was woken.
+fn block_on<F: Future>(mut future: F) -> (F::Output, usize) {
+    let waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        wakes: AtomicUsize::new(0)
+    });
+    let task = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&task);
+    // Safe because the future is never moved after being pinned here
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return (output, waker.wakes.load(Ordering::SeqCst));
+        }
+        thread::park();
+    }
+}
 
 #[test]
 pub fn test_new() {
@@ -20,7 +81,7 @@ pub fn test_new() {
     thread::sleep(Duration::from_secs(1));
 
     assert!(File::open("/tmp/promise-new").is_ok());
-    fs::remove_file("/tmp/promise-new");
+    fs::remove_file("/tmp/promise-new").unwrap();
 }
 
 #[test]
@@ -28,19 +89,20 @@ pub fn test_then() {
     Promise::new(|| {
         fs::create_dir("/tmp/promise-then")
     })
-    .then(|t| {
+    .then(|_| {
         File::create("/tmp/promise-then/file")
-    }, |e| {
+    }, |_| {
         println!("Unable to create dir!");
-        Err(io::Error::new(io::ErrorKind::Other, "Couldn't make dir!"))
+        Err(io::Error::other("Couldn't make dir!"))
     });
 
     thread::sleep(Duration::from_secs(1));
     assert!(File::open("/tmp/promise-then/file").is_ok());
-    fs::remove_dir_all("/tmp/promise-then");
+    fs::remove_dir_all("/tmp/promise-then").unwrap();
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 enum TestErrType {
     CreateDir,
     CreateFile,
@@ -56,8 +118,8 @@ pub fn test_then_ok() {
             Err(_) => Err(TestErrType::CreateDir)
         }
     })
-    .then_ok(|k| {
-        // k is (), as that's what was passed through Ok(())
+    .then_ok(|_k| {
+        // _k is (), as that's what was passed through Ok(())
         match File::create("/tmp/promise-then-ok/file") {
             Ok(file) => Ok(file), // Now file is passed along
             Err(_) => Err(TestErrType::CreateFile)
@@ -110,3 +172,2080 @@ pub fn promise_all() {
       Err(())
     });
 }
+
+#[test]
+pub fn test_ready_on() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::new_blocking(|| {
+            thread::sleep(Duration::from_millis(600));
+            Ok(0)
+        }),
+        Promise::new_blocking(|| {
+            thread::sleep(Duration::from_millis(100));
+            Ok(1)
+        }),
+        Promise::new_blocking(|| {
+            thread::sleep(Duration::from_millis(600));
+            Ok(2)
+        }),
+    ];
+
+    let before = threads_spawned();
+    assert_eq!(Promise::ready_on(&promises), 1);
+    assert_eq!(threads_spawned(), before);
+    assert_eq!(Promise::all(promises).wait(), Ok(vec![0, 1, 2]));
+
+    let dead: Vec<Promise<u32, ()>> = (0..3)
+        .map(|_| Promise::new(|| panic!("worker died")))
+        .collect();
+    for promise in &dead {
+        Promise::ready_on(slice::from_ref(promise));
+    }
+    assert_eq!(Promise::ready_on(&dead), 0);
+}
+
+#[test]
+pub fn test_new_catch_str() {
+    let promise: Promise<(), PanicMessage> = Promise::new_catch(|| {
+        panic!("static message")
+    });
+
+    let err = settle(promise).unwrap_err();
+    assert_eq!(err.message, "static message");
+    assert_eq!(err.thread_name, None);
+}
+
+#[test]
+pub fn test_new_catch_string() {
+    let promise: Promise<(), PanicMessage> = Promise::new_catch(|| {
+        panic!("formatted {}", 42)
+    });
+
+    assert_eq!(settle(promise).unwrap_err().message, "formatted 42");
+}
+
+// An error which can't be made from a panic, so `new_catch` dies anyway.
+#[derive(Debug, PartialEq)]
+struct Uncatchable;
+
+impl From<PanicMessage> for Uncatchable {
+    fn from(message: PanicMessage) -> Uncatchable {
+        panic!("converting {}", message.message)
+    }
+}
+
+#[test]
+pub fn test_new_catch_threads() {
+    let before = threads_spawned();
+    let promise: Promise<(), PanicMessage> = Promise::new_catch(|| Ok(()));
+    // Queued on the global runtime, like `new`
+    assert_eq!(threads_spawned() - before, 0);
+    assert_eq!(promise.wait(), Ok(()));
+
+    let promise: Promise<(), Uncatchable> = Promise::new_catch(|| {
+        panic!("caught")
+    });
+    assert_eq!(promise.wait(), Err(PromiseError::Panicked(
+        Some("converting caught".to_string()))));
+}
+
+#[test]
+pub fn test_all_with_handles() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::new_blocking(|| Ok(1)),
+        Promise::new_blocking(|| panic!("producer failed")),
+        Promise::resolve(3).into_promise(),
+    ];
+
+    let (_all, mut handles) = Promise::all_with_handles(promises);
+    assert_eq!(handles.len(), 3);
+    assert!(handles[2].is_none());
+
+    assert!(handles[0].take().unwrap().join().is_ok());
+    let payload = handles[1].take().unwrap().join().unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"producer failed"));
+}
+
+#[test]
+pub fn test_with_finalizer_panic() {
+    let finalized = Arc::new(AtomicBool::new(false));
+    let flag = finalized.clone();
+
+    let promise: Promise<(), ()> = Promise::resolve(()).into_promise()
+        .with_finalizer(|_| -> Result<(), ()> {
+            panic!("body failed")
+        }, move || {
+            flag.store(true, Ordering::SeqCst);
+        });
+
+    // The promise is dead once the worker has unwound.
+    Promise::ready_on(&[promise]);
+    assert!(finalized.load(Ordering::SeqCst));
+}
+
+#[test]
+pub fn test_all_partial() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::new_blocking(|| {
+            thread::sleep(Duration::from_millis(200));
+            Err("failed")
+        }),
+        Promise::resolve(3).into_promise(),
+    ];
+
+    let (err, partial) = settle(Promise::all_partial(promises)).unwrap_err();
+    assert_eq!(err, "failed");
+    assert_eq!(partial, vec![1, 3]);
+}
+
+#[test]
+pub fn test_interval_count() {
+    let period = Duration::from_millis(20);
+    let stream = Promise::<usize, ()>::interval_count(period, 4);
+
+    // Collecting only finishes once the timer thread has dropped its sender.
+    let ticks: Vec<usize> = stream.map(|tick| tick.unwrap()).collect();
+    assert_eq!(ticks, vec![0, 1, 2, 3]);
+}
+
+#[test]
+pub fn test_race_window() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::new(|| {
+            thread::sleep(Duration::from_millis(50));
+            Err("fast failure")
+        }),
+        Promise::new(|| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(2)
+        }),
+    ];
+
+    let window = Duration::from_millis(1000);
+    assert_eq!(settle(Promise::race_window(promises, window)), Ok(2));
+}
+
+#[test]
+pub fn test_span() {
+    let promise: Promise<u32, ()> = Promise::resolve(1).into_promise()
+        .span("test-span-sleep", |x| {
+            thread::sleep(Duration::from_millis(100));
+            Ok(x + 1)
+        })
+        .span("test-span-double", |x| Ok(x * 2));
+    assert_eq!(settle(promise), Ok(4));
+
+    let spans: Vec<_> = Promise::take_spans().into_iter()
+        .filter(|span| span.name.starts_with("test-span-"))
+        .collect();
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[0].name, "test-span-sleep");
+    assert!(spans[0].duration >= Duration::from_millis(100));
+    assert_eq!(spans[1].name, "test-span-double");
+    assert!(spans[1].duration < Duration::from_millis(100));
+}
+
+#[test]
+pub fn test_fan_out() {
+    let promise: Promise<Vec<u32>, ()> = Promise::new(|| Ok(vec![1, 2, 3]))
+        .fan_out(|ids| {
+            ids.into_iter().map(|id| Promise::new(move || {
+                thread::sleep(Duration::from_millis(50));
+                Ok(id * 10)
+            })).collect()
+        });
+
+    let mut values = settle(promise).unwrap();
+    values.sort();
+    assert_eq!(values, vec![10, 20, 30]);
+}
+
+#[test]
+pub fn test_repeat_until() {
+    let promise: Promise<u32, ()> = Promise::repeat_until(0, |count| {
+        if count == 5 {
+            Ok(ControlFlow::Break(count))
+        }
+        else {
+            Ok(ControlFlow::Continue(count + 1))
+        }
+    });
+
+    assert_eq!(settle(promise), Ok(5));
+}
+
+#[test]
+pub fn test_all_macro() {
+    let number: Promise<u32, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(50));
+        Ok(1)
+    });
+    let text: Promise<String, &str> =
+        Promise::resolve("two".to_string()).into_promise();
+    let flag: Promise<bool, &str> = Promise::resolve(true).into_promise();
+
+    let all = all!(number, text, flag);
+    assert_eq!(settle(all), Ok((1, "two".to_string(), true)));
+}
+
+#[test]
+pub fn test_all_macro_short_circuit() {
+    let slow: Promise<u32, &str> = Promise::new_blocking(|| {
+        thread::sleep(Duration::from_secs(2));
+        Ok(1)
+    });
+    let failed: Promise<char, &str> = Promise::reject("failed").into_promise();
+    let text: Promise<String, &str> =
+        Promise::resolve("three".to_string()).into_promise();
+
+    let start = Instant::now();
+    assert_eq!(settle(all!(slow, failed, text)), Err("failed"));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+pub fn test_from_result_ref() {
+    let canonical: Result<String, ()> = Ok("cached".to_string());
+
+    let promise = Promise::from_result_ref(&canonical);
+    assert_eq!(settle(promise), Ok("cached".to_string()));
+    assert_eq!(canonical, Ok("cached".to_string()));
+}
+
+#[test]
+pub fn test_race_builder() {
+    let slow: Promise<&str, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_secs(2));
+        Ok("slow")
+    });
+    let slower: Promise<&str, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_secs(3));
+        Ok("slower")
+    });
+
+    let race = RaceBuilder::new()
+        .add(slow)
+        .add(slower)
+        .add_delay(Duration::from_millis(100), Err("timed out"))
+        .build();
+    assert_eq!(settle(race), Err("timed out"));
+}
+
+#[test]
+pub fn test_coalescer() {
+    let coalescer: Coalescer<&str, u32, ()> = Coalescer::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let fetch = |runs: Arc<AtomicUsize>| move || {
+        runs.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        Ok(7)
+    };
+    let first = coalescer.get_or_spawn("key", fetch(runs.clone()));
+    let second = coalescer.get_or_spawn("key", fetch(runs.clone()));
+
+    assert_eq!(settle(first), Ok(7));
+    assert_eq!(settle(second), Ok(7));
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn test_ordered_stream_all_terminated() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::new(|| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(1)
+        }),
+        Promise::new(|| panic!("producer died")),
+        Promise::resolve(3).into_promise(),
+    ];
+
+    let events: Vec<_> =
+        Promise::ordered_stream_all_terminated(promises).into_iter().collect();
+    assert_eq!(events, vec![
+        StreamEvent::Item(Ok(1)),
+        StreamEvent::Item(Ok(3)),
+        StreamEvent::Done { completed: 2, dead: 1 },
+    ]);
+}
+
+#[derive(Debug, PartialEq)]
+enum ConfigError {
+    Io(io::ErrorKind)
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> ConfigError {
+        ConfigError::Io(err.kind())
+    }
+}
+
+#[test]
+pub fn test_new_try() {
+    let promise: Promise<String, ConfigError> = Promise::new_try(|| {
+        let mut file = File::open("/tmp/promise-new-try/missing")?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok::<String, io::Error>(contents)
+    });
+
+    assert_eq!(settle(promise), Err(ConfigError::Io(io::ErrorKind::NotFound)));
+}
+
+#[test]
+pub fn test_all_collector() {
+    let collector: AllCollector<u32, ()> = AllCollector::new();
+    let result = collector.result();
+
+    let early = collector.clone();
+    early.submit(Promise::resolve(1).into_promise());
+    early.submit(Promise::new(|| {
+        thread::sleep(Duration::from_millis(100));
+        Ok(2)
+    }));
+
+    thread::sleep(Duration::from_millis(50));
+    collector.submit(Promise::resolve(3).into_promise());
+    collector.close();
+
+    assert_eq!(settle(result), Ok(vec![1, 2, 3]));
+}
+
+#[test]
+#[should_panic(expected = "closed")]
+pub fn test_all_collector_submit_after_close() {
+    let collector: AllCollector<u32, ()> = AllCollector::new();
+    let late = collector.clone();
+    collector.close();
+
+    late.submit(Promise::resolve(1).into_promise());
+}
+
+#[test]
+pub fn test_ready_promise() {
+    let caller = thread::current().id();
+
+    let ready: ReadyPromise<u32, ()> = Promise::ready(Ok(2))
+        .map(|x| {
+            assert_eq!(thread::current().id(), caller);
+            x * 10
+        })
+        .and_then(|x| {
+            assert_eq!(thread::current().id(), caller);
+            ReadyPromise::resolve(x + 1)
+        });
+    assert_eq!(ready.clone().into_result(), Ok(21));
+    assert_eq!(settle(ready.into_promise()), Ok(21));
+
+    let rejected: ReadyPromise<u32, &str> = Promise::reject("failed");
+    assert_eq!(rejected.map(|x| x + 1).into_result(), Err("failed"));
+
+    let resolved: ReadyPromise<u32, &str> = Promise::resolve(4);
+    assert_eq!(resolved.and_then(|x| Promise::resolve(x * 2)).into_result(),
+               Ok(8));
+}
+
+// Counts how many workers have run their cleanup.
+struct CountOnDrop(Arc<AtomicUsize>);
+
+impl Drop for CountOnDrop {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+pub fn test_race_cancel() {
+    let finalized = Arc::new(AtomicUsize::new(0));
+    let loser = |finalized: Arc<AtomicUsize>| {
+        Promise::new_cancellable(move |flag| {
+            let _finalizer = CountOnDrop(finalized);
+            while !flag.is_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // Cleaning up takes a while after being cancelled
+            thread::sleep(Duration::from_millis(100));
+            Err("cancelled")
+        })
+    };
+
+    let promises: Vec<Promise<&str, &str>> = vec![
+        loser(finalized.clone()),
+        Promise::new(|| {
+            thread::sleep(Duration::from_millis(50));
+            Ok("winner")
+        }),
+        loser(finalized.clone()),
+    ];
+
+    assert_eq!(settle(Promise::race_cancel(promises)), Ok("winner"));
+    assert_eq!(finalized.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+pub fn test_new_within() {
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let promise: Promise<(), DeadlineExceeded> =
+        Promise::new_within(deadline, |flag| {
+            assert!(flag.deadline().is_some());
+            thread::sleep(Duration::from_secs(2));
+            Ok(())
+        });
+
+    let start = Instant::now();
+    assert_eq!(settle(promise), Err(DeadlineExceeded));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+pub fn test_and_then_some() {
+    let double = |x: u32| Ok(x * 2);
+
+    let found: Promise<Option<u32>, &str> =
+        Promise::resolve(Some(4)).into_promise();
+    assert_eq!(settle(found.and_then_some(double)), Ok(Some(8)));
+
+    let missing: Promise<Option<u32>, &str> =
+        Promise::resolve(None).into_promise();
+    assert_eq!(settle(missing.and_then_some(double)), Ok(None));
+
+    let failed: Promise<Option<u32>, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(settle(failed.and_then_some(double)), Err("failed"));
+}
+
+#[test]
+pub fn test_all_detailed_rejected() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::resolve(0).into_promise(),
+        Promise::resolve(1).into_promise(),
+        Promise::reject("failed").into_promise(),
+    ];
+
+    assert_eq!(settle(Promise::all_detailed(promises)),
+               Err(AllError::Rejected(2, "failed")));
+}
+
+#[test]
+pub fn test_all_detailed_panicked() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::resolve(0).into_promise(),
+        Promise::new(|| panic!("producer died")),
+        Promise::resolve(2).into_promise(),
+    ];
+
+    assert_eq!(settle(Promise::all_detailed(promises)),
+               Err(AllError::Panicked(1)));
+}
+
+#[test]
+pub fn test_new_guarded_cancels_on_drop() {
+    let (promise, guard) = Promise::new_guarded(|flag| {
+        while !flag.is_cancelled() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        Err("cancelled")
+    });
+    drop(guard);
+
+    assert_eq!(settle::<(), _>(promise), Err("cancelled"));
+}
+
+#[test]
+pub fn test_map_each() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::resolve(2).into_promise(),
+        Promise::resolve(3).into_promise(),
+    ];
+    let doubled = Promise::map_each(promises, |val| Ok(val * 2));
+    let results: Vec<_> = doubled.into_iter().map(settle).collect();
+
+    assert_eq!(results, vec![Ok(2), Ok(4), Ok(6)]);
+}
+
+#[test]
+pub fn test_all_from_fns_named() {
+    let makers: Vec<_> = (0..3).map(|_| || {
+        Ok(thread::current().name().map(String::from))
+    }).collect();
+    let promise: Promise<_, ()> =
+        Promise::all_from_fns_named("names", makers);
+    let mut names = settle(promise).unwrap();
+    names.sort();
+
+    assert_eq!(names, vec![Some("batch-names-0".to_string()),
+                           Some("batch-names-1".to_string()),
+                           Some("batch-names-2".to_string())]);
+}
+
+#[test]
+pub fn test_all_deadline() {
+    let slow_cancelled = Arc::new(AtomicBool::new(false));
+    let worker_cancelled = slow_cancelled.clone();
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::resolve(0).into_promise(),
+        Promise::new_cancellable(move |flag| {
+            while !flag.is_cancelled() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            worker_cancelled.store(true, Ordering::SeqCst);
+            Ok(1)
+        }),
+        Promise::new_blocking(|| {
+            thread::sleep(Duration::from_millis(20));
+            Ok(2)
+        }),
+    ];
+    let deadline = Instant::now() + Duration::from_millis(300);
+
+    assert_eq!(settle(Promise::all_deadline(promises, deadline)),
+               Ok(vec![Some(0), None, Some(2)]));
+
+    let start = Instant::now();
+    while !slow_cancelled.load(Ordering::SeqCst) {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+pub fn test_try_map_question_mark() {
+    let parse = |text: &str| -> Promise<u32, ParseIntError> {
+        Promise::resolve(text.to_string()).into_promise().try_map(|text| {
+            let value: u32 = text.trim().parse()?;
+            Ok(value + 1)
+        })
+    };
+
+    assert_eq!(settle(parse(" 41 ")), Ok(42));
+    assert!(settle(parse("forty-one")).is_err());
+}
+
+#[test]
+pub fn test_latency_all() {
+    let promises: Vec<Promise<u64, ()>> = [200, 0, 100].iter()
+        .map(|&millis| Promise::new_blocking(move || {
+            thread::sleep(Duration::from_millis(millis));
+            Ok(millis)
+        }))
+        .collect();
+    let (values, stats) = settle(Promise::latency_all(promises)).unwrap();
+
+    assert_eq!(values, vec![200, 0, 100]);
+    assert!(stats.min < Duration::from_millis(100));
+    assert!(stats.p50 >= Duration::from_millis(100));
+    assert!(stats.p50 < Duration::from_millis(200));
+    assert!(stats.max >= Duration::from_millis(200));
+    assert_eq!(stats.p95, stats.max);
+    assert!(stats.mean >= Duration::from_millis(100));
+    assert!(stats.mean < stats.max);
+}
+
+#[test]
+pub fn test_until_signal_terminates() {
+    let work: Promise<u32, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(500));
+        Ok(1)
+    });
+    let signal: Promise<(), ()> = Promise::resolve(()).into_promise();
+
+    assert_eq!(settle(work.until_signal(signal)),
+               Err(TerminatedOr::Terminated));
+
+    let work: Promise<u32, &str> = Promise::reject("failed").into_promise();
+    let signal: Promise<(), ()> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(500));
+        Ok(())
+    });
+
+    assert_eq!(settle(work.until_signal(signal)),
+               Err(TerminatedOr::Rejected("failed")));
+}
+
+#[test]
+pub fn test_reduce_parallel_sum() {
+    let promises: Vec<Promise<u32, ()>> = (1..17)
+        .map(|val| Promise::new(move || {
+            thread::sleep(Duration::from_millis(u64::from(val % 4) * 10));
+            Ok(val)
+        }))
+        .collect();
+
+    // The values may be added in any order, which is fine for a sum
+    assert_eq!(settle(Promise::reduce_parallel(promises, |a, b| a + b)),
+               Ok(136));
+
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::reject("failed").into_promise(),
+    ];
+    assert_eq!(settle(Promise::reduce_parallel(promises, |a, b| a + b)),
+               Err("failed"));
+}
+
+#[test]
+#[cfg(panic = "unwind")]
+pub fn test_panic_strategy_unwind_catches() {
+    assert_eq!(Promise::panic_strategy(), PanicStrategy::Unwind);
+
+    let promise: Promise<(), PanicMessage> =
+        Promise::new_catch(|| panic!("caught"));
+    assert_eq!(settle(promise).unwrap_err().message, "caught");
+}
+
+#[test]
+pub fn test_race_tagged() {
+    let backend = |millis: u64| Promise::new_blocking(move || {
+        thread::sleep(Duration::from_millis(millis));
+        Ok(millis)
+    });
+    let promises: Vec<(&str, Promise<u64, ()>)> = vec![
+        ("primary", backend(300)),
+        ("replica", backend(10)),
+        ("archive", backend(600)),
+    ];
+
+    assert_eq!(settle(Promise::race_tagged(promises)),
+               Ok(("replica", Ok(10))));
+}
+
+#[test]
+pub fn test_warn_if_slow() {
+    let link = |millis: u64| {
+        let (warn_tx, warn_rx) = mpsc::channel();
+        let upstream: Promise<u64, ()> = Promise::new(move || {
+            thread::sleep(Duration::from_millis(millis));
+            Ok(millis)
+        });
+        let promise = upstream.warn_if_slow(
+            Duration::from_millis(100),
+            move |waited| warn_tx.send(waited).unwrap(),
+            |val| Ok(val + 1));
+        assert_eq!(settle(promise), Ok(millis + 1));
+        warn_rx.try_recv().ok()
+    };
+
+    assert!(link(300).unwrap() >= Duration::from_millis(300));
+    assert_eq!(link(0), None);
+}
+
+#[test]
+pub fn test_take_n() {
+    let cancelled = Arc::new(AtomicUsize::new(0));
+    let source = |millis: u64| {
+        let cancelled = cancelled.clone();
+        Promise::new_cancellable(move |flag| {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(millis) {
+                if flag.is_cancelled() {
+                    cancelled.fetch_add(1, Ordering::SeqCst);
+                    return Err("cancelled");
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(millis)
+        })
+    };
+    let promises: Vec<Promise<u64, &str>> = vec![
+        source(2000), source(10), source(2000), source(30), source(50),
+    ];
+    let mut values = settle(Promise::take_n(promises, 3)).unwrap();
+    values.sort();
+
+    assert_eq!(values, vec![10, 30, 50]);
+    let start = Instant::now();
+    while cancelled.load(Ordering::SeqCst) < 2 {
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let promises: Vec<Promise<u64, &str>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::reject("failed").into_promise(),
+        Promise::resolve(2).into_promise(),
+    ];
+    let mut values = settle(Promise::take_n(promises, 2)).unwrap();
+    values.sort();
+    assert_eq!(values, vec![1, 2]);
+
+    let promises: Vec<Promise<u64, &str>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::resolve(2).into_promise(),
+        Promise::reject("failed").into_promise(),
+    ];
+    assert_eq!(settle(Promise::take_n(promises, 3)), Err("failed"));
+}
+
+#[test]
+#[should_panic(expected = "can't take 3 values from 2 promises")]
+pub fn test_take_n_too_many() {
+    let promises: Vec<Promise<u64, &str>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::resolve(2).into_promise(),
+    ];
+    Promise::take_n(promises, 3);
+}
+
+#[test]
+pub fn test_recover_pipeline() {
+    let primary: Promise<String, String> = Promise::new(|| {
+        Err("primary unavailable".to_string())
+    });
+    let recovered = primary.recover_pipeline(|err| {
+        assert_eq!(err, "primary unavailable");
+        let cached: Promise<&str, String> =
+            Promise::resolve("cached").into_promise();
+        cached
+            .then_ok(|name| Ok(name.len()))
+            .then_ok(|len| Ok(format!("{} bytes from cache", len)))
+    });
+
+    assert_eq!(settle(recovered), Ok("6 bytes from cache".to_string()));
+
+    let primary: Promise<u32, ()> = Promise::resolve(1).into_promise();
+    let recovered = primary.recover_pipeline(|_| panic!("not needed"));
+    assert_eq!(settle(recovered), Ok(1));
+}
+
+#[test]
+pub fn test_all_dedup() {
+    let promises: Vec<Promise<u32, ()>> = [1, 2, 2, 3, 1].iter()
+        .map(|&val| Promise::resolve(val).into_promise())
+        .collect();
+
+    assert_eq!(settle(Promise::all_dedup(promises)), Ok(vec![1, 2, 3]));
+}
+
+#[test]
+pub fn test_map_retry() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let attempt_calls = calls.clone();
+    let raw: Promise<Vec<u8>, &str> =
+        Promise::resolve(b"42".to_vec()).into_promise();
+    let parsed = raw.map_retry(5, move |bytes| {
+        if attempt_calls.fetch_add(1, Ordering::SeqCst) < 2 {
+            return Err("flaky validator");
+        }
+        Ok(String::from_utf8_lossy(bytes).parse::<u32>().unwrap())
+    });
+
+    assert_eq!(settle(parsed), Ok(42));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    let raw: Promise<u32, &str> = Promise::resolve(1).into_promise();
+    let failed = raw.map_retry(2, |_| Err::<u32, _>("always fails"));
+    assert_eq!(settle(failed), Err("always fails"));
+}
+
+#[test]
+pub fn test_all_settled_inputs_skip_threads() {
+    let before = threads_spawned();
+    let settled: Vec<Promise<u32, ()>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::resolve(2).into_promise(),
+    ];
+    let all = Promise::all(settled);
+    assert_eq!(threads_spawned() - before, 0);
+    assert_eq!(settle(all), Ok(vec![1, 2]));
+
+    let (start_tx, start_rx) = mpsc::channel::<()>();
+    let mixed: Vec<Promise<u32, ()>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::new(move || {
+            start_rx.recv().unwrap();
+            Ok(3)
+        }),
+        Promise::resolve(2).into_promise(),
+    ];
+    let before = threads_spawned();
+    let all = Promise::all(mixed);
+    // The pending promise settles the batch itself, so nothing waits on it
+    assert_eq!(threads_spawned() - before, 0);
+    start_tx.send(()).unwrap();
+
+    assert_eq!(settle(all), Ok(vec![1, 3, 2]));
+}
+
+#[test]
+pub fn test_local_pool_one_thread() {
+    let pool = LocalPool::new();
+    let promises: Vec<Promise<thread::ThreadId, ()>> = (0..3)
+        .map(|_| pool.spawn(|| Ok(thread::current().id())))
+        .collect();
+    let ids: Vec<_> = promises.into_iter()
+        .map(|promise| pool.run_until(promise).unwrap())
+        .collect();
+
+    assert!(ids.iter().all(|&id| id == ids[0]));
+    assert!(ids[0] != thread::current().id());
+
+    let panicked: Promise<u32, ()> = pool.spawn(|| panic!("pool task"));
+    assert_eq!(pool.run_until(panicked),
+               Err(PromiseError::Panicked(Some("pool task".to_string()))));
+}
+
+#[test]
+#[should_panic(expected = "outside the pool")]
+pub fn test_local_pool_run_until_foreign() {
+    let pool = LocalPool::new();
+    let other = LocalPool::new();
+    let promise: Promise<u32, ()> = other.spawn(|| Ok(1));
+    pool.run_until(promise).unwrap_or(0);
+}
+
+#[test]
+pub fn test_race_group_late_competitor() {
+    let group: RaceGroup<&str, ()> = RaceGroup::new();
+    group.add(Promise::new(|| {
+        thread::sleep(Duration::from_millis(500));
+        Ok("slow")
+    }));
+    let result = group.result();
+
+    // The race is already being waited on when the faster replica joins
+    let late = group.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        late.add(Promise::resolve("fast").into_promise());
+    });
+
+    assert_eq!(settle(result), Ok("fast"));
+    group.add(Promise::resolve("too late").into_promise());
+}
+
+#[test]
+pub fn test_map_or_pass() {
+    let trim = |text: &'static str| {
+        let promise: Promise<String, ()> =
+            Promise::resolve(text.to_string()).into_promise();
+        settle(promise.map_or_pass(|text| {
+            if text.trim() == text { Err(text) }
+            else { Ok(text.trim().to_string()) }
+        }))
+    };
+
+    assert_eq!(trim("  padded "), Ok("padded".to_string()));
+    assert_eq!(trim("clean"), Ok("clean".to_string()));
+}
+
+#[test]
+pub fn test_all_child_of_scope_drop() {
+    let cancelled = Arc::new(AtomicUsize::new(0));
+    let scope = PromiseScope::new();
+    let worker = || {
+        let cancelled = cancelled.clone();
+        Promise::new_cancellable(move |flag| {
+            while !flag.is_cancelled() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            cancelled.fetch_add(1, Ordering::SeqCst);
+            Err("cancelled")
+        })
+    };
+    let promises: Vec<Promise<(), &str>> =
+        vec![worker(), worker(), scope.spawn(|_| Ok(()))];
+    let batch = Promise::all_child_of(&scope, promises);
+
+    drop(scope);
+
+    assert_eq!(settle(batch), Err("cancelled"));
+    let start = Instant::now();
+    while cancelled.load(Ordering::SeqCst) < 2 {
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+pub fn test_wait() {
+    let resolved: Promise<u32, &str> = Promise::new(|| Ok(42));
+    assert_eq!(resolved.wait(), Ok(42));
+
+    let rejected: Promise<u32, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(rejected.wait(), Err(PromiseError::Rejected("failed")));
+
+    let dead: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    assert_eq!(dead.wait(),
+               Err(PromiseError::Panicked(Some("worker died".to_string()))));
+}
+
+#[test]
+pub fn test_new_then_one_thread() {
+    let fused: Promise<u32, ()> = Promise::new_then(|| Ok("42"), |text| {
+        Ok(text.parse().unwrap())
+    });
+    assert_eq!(fused.wait(), Ok(42));
+
+    let same: Promise<bool, ()> = Promise::new_then(
+        || Ok(thread::current().id()),
+        |id| Ok(id == thread::current().id()));
+    assert_eq!(same.wait(), Ok(true));
+}
+
+#[test]
+pub fn test_try_wait() {
+    let (start_tx, start_rx) = mpsc::channel::<()>();
+    let promise: Promise<u32, ()> = Promise::new(move || {
+        start_rx.recv().unwrap();
+        Ok(42)
+    });
+    assert_eq!(promise.try_wait(), None);
+
+    start_tx.send(()).unwrap();
+    let start = Instant::now();
+    let result = loop {
+        if let Some(result) = promise.try_wait() {
+            break result;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    assert_eq!(result, Ok(42));
+    assert_eq!(promise.try_wait(), Some(Ok(42)));
+    assert_eq!(promise.wait(), Ok(42));
+
+    let dead: Promise<u32, ()> = Promise::new(|| panic!("worker died"));
+    Promise::ready_on(slice::from_ref(&dead));
+    assert_eq!(dead.try_wait(), Some(Err(PromiseError::Panicked(
+        Some("worker died".to_string())))));
+}
+
+#[test]
+pub fn test_ordered_stream_all_progress() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let progress_log = log.clone();
+    let promises: Vec<Promise<u64, ()>> = [200, 0, 50].iter()
+        .map(|&millis| Promise::new(move || {
+            thread::sleep(Duration::from_millis(millis));
+            Ok(millis)
+        }))
+        .collect();
+    let stream = Promise::ordered_stream_all_progress(promises,
+        move |completed, total| {
+            progress_log.lock().unwrap()
+                .push(format!("{}/{}", completed, total));
+        });
+
+    for result in stream {
+        log.lock().unwrap().push(format!("item {}", result.unwrap()));
+    }
+
+    assert_eq!(*log.lock().unwrap(),
+               vec!["1/3", "2/3", "3/3", "item 200", "item 0", "item 50"]);
+}
+
+#[test]
+pub fn test_then_ok_then_err_passthrough() {
+    // A moved box keeps its address, so this shows nothing was copied
+    let addr = |val: &u32| val as *const u32 as usize;
+
+    let err = Box::new(1);
+    let err_addr = addr(&err);
+    let rejected: Promise<(), Box<u32>> = Promise::reject(err).into_promise();
+    let passed = rejected.then_ok(|_| -> Result<u32, Box<u32>> {
+        panic!("then_ok ran on an error")
+    });
+    match passed.wait() {
+        Err(PromiseError::Rejected(err)) => assert_eq!(addr(&err), err_addr),
+        other => panic!("expected the original error, got {:?}", other)
+    }
+
+    let val = Box::new(2);
+    let val_addr = addr(&val);
+    let resolved: Promise<Box<u32>, ()> = Promise::resolve(val).into_promise();
+    let passed = resolved.then_err(|_| -> Result<Box<u32>, ()> {
+        panic!("then_err ran on a value")
+    });
+    assert_eq!(addr(&passed.wait().unwrap()), val_addr);
+}
+
+#[test]
+pub fn test_recover_if() {
+    let fetch = |status: u16| {
+        let response: Promise<Vec<u8>, u16> =
+            Promise::reject(status).into_promise();
+        response.recover_if(|&status| status == 404, |_| Vec::new()).wait()
+    };
+
+    assert_eq!(fetch(404), Ok(Vec::new()));
+    assert_eq!(fetch(500), Err(PromiseError::Rejected(500)));
+}
+
+#[test]
+pub fn test_map() {
+    let resolved: Promise<u32, &str> = Promise::resolve(20).into_promise();
+    assert_eq!(resolved.map(|val| val * 2 + 2).wait(), Ok(42));
+
+    let rejected: Promise<u32, &str> =
+        Promise::reject("failed").into_promise();
+    let mapped = rejected.map(|_| -> u32 { panic!("map ran on an error") });
+    assert_eq!(mapped.wait(), Err(PromiseError::Rejected("failed")));
+}
+
+#[derive(Debug, PartialEq)]
+struct Record(String);
+
+impl Spill for Record {
+    fn spill_size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Record> {
+        String::from_utf8(bytes.to_vec())
+            .map(Record)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[test]
+pub fn test_all_spilled_round_trip() {
+    let promises: Vec<Promise<Record, ConfigError>> = (0..10)
+        .map(|index| Record(format!("record {}", index)))
+        .map(|record| Promise::resolve(record).into_promise())
+        .collect();
+    // Each record is 8 bytes, so only two fit under the threshold
+    let values = Promise::all_spilled(promises, 20).wait().unwrap();
+    assert_eq!(values.in_memory(), 2);
+    assert_eq!(values.spilled(), 8);
+
+    let mut records: Vec<String> =
+        values.into_iter().map(|record| record.unwrap().0).collect();
+    records.sort();
+    let expected: Vec<String> =
+        (0..10).map(|index| format!("record {}", index)).collect();
+    assert_eq!(records, expected);
+}
+
+#[test]
+pub fn test_map_err() {
+    let failed: Promise<u32, io::Error> = Promise::new(|| {
+        Err(io::Error::new(io::ErrorKind::NotFound, "no config"))
+    });
+    assert_eq!(failed.map_err(ConfigError::from).wait(),
+               Err(PromiseError::Rejected(ConfigError::Io(
+                   io::ErrorKind::NotFound))));
+
+    let resolved: Promise<u32, io::Error> = Promise::resolve(7).into_promise();
+    assert_eq!(resolved.map_err(ConfigError::from).wait(), Ok(7));
+}
+
+#[test]
+pub fn test_then_acc() {
+    let inputs = Accumulator::new(Vec::new());
+    let start: Promise<u32, ()> = Promise::resolve(1).into_promise();
+    let chain = start
+        .then_acc(&inputs, |val, seen| { seen.push(val); Ok(val + 1) })
+        .then_acc(&inputs, |val, seen| { seen.push(val); Ok(val * 10) })
+        .then_acc(&inputs, |val, seen| { seen.push(val); Ok(val) });
+
+    assert_eq!(chain.wait(), Ok(20));
+    assert_eq!(*inputs.lock(), vec![1, 2, 20]);
+}
+
+#[test]
+pub fn test_and_then() {
+    let user_id: Promise<u32, &str> = Promise::resolve(7).into_promise();
+    let name = user_id.and_then(|id| Promise::new(move || {
+        thread::sleep(Duration::from_millis(20));
+        Ok(format!("user {}", id))
+    }));
+    assert_eq!(name.wait(), Ok("user 7".to_string()));
+
+    let missing: Promise<u32, &str> =
+        Promise::reject("no such user").into_promise();
+    let name = missing.and_then(|_| -> Promise<String, &str> {
+        panic!("and_then ran on an error")
+    });
+    assert_eq!(name.wait(), Err(PromiseError::Rejected("no such user")));
+}
+
+#[test]
+pub fn test_race_outcome() {
+    let delayed = |millis: u64, result: Result<u32, &'static str>| {
+        Promise::new_blocking(move || {
+            thread::sleep(Duration::from_millis(millis));
+            result
+        })
+    };
+
+    let promises = vec![delayed(300, Ok(0)), delayed(10, Ok(1))];
+    assert_eq!(Promise::race_outcome(promises).wait(),
+               Ok(RaceOutcome::Winner { index: 1, result: Ok(1) }));
+
+    let promises = vec![delayed(10, Err("failed")), delayed(300, Ok(1))];
+    assert_eq!(Promise::race_outcome(promises).wait(),
+               Ok(RaceOutcome::Winner { index: 0, result: Err("failed") }));
+
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::new(|| panic!("first died")),
+        Promise::new(|| panic!("second died")),
+    ];
+    assert_eq!(Promise::race_outcome(promises).wait(),
+               Ok(RaceOutcome::AllDead));
+}
+
+#[test]
+pub fn test_catch() {
+    let retry = |err: &'static str| {
+        let rejected: Promise<u32, &str> = Promise::reject(err).into_promise();
+        rejected.catch(|err| if err == "timeout" { Ok(0) } else { Err(err) })
+            .wait()
+    };
+
+    assert_eq!(retry("timeout"), Ok(0));
+    assert_eq!(retry("refused"), Err(PromiseError::Rejected("refused")));
+
+    let resolved: Promise<u32, &str> = Promise::resolve(5).into_promise();
+    assert_eq!(resolved.catch(|_| panic!("catch ran on a value")).wait(),
+               Ok(5));
+}
+
+#[test]
+pub fn test_deferred_work() {
+    let (promise, work) = Promise::<u32, ()>::deferred_work(|| Ok(42));
+    assert_eq!(promise.try_wait(), None);
+
+    thread::spawn(work).join().unwrap();
+    assert_eq!(promise.wait(), Ok(42));
+
+    let (promise, work) = Promise::<u32, ()>::deferred_work(|| Ok(42));
+    drop(work);
+    assert_eq!(promise.wait(), Err(PromiseError::Dead));
+}
+
+#[test]
+pub fn test_finally() {
+    let cleaned = Arc::new(AtomicUsize::new(0));
+    let cleanup = || {
+        let cleaned = cleaned.clone();
+        move || { cleaned.fetch_add(1, Ordering::SeqCst); }
+    };
+
+    let resolved: Promise<u32, &str> = Promise::resolve(1).into_promise();
+    assert_eq!(resolved.finally(cleanup()).wait(), Ok(1));
+    let rejected: Promise<u32, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(rejected.finally(cleanup()).wait(),
+               Err(PromiseError::Rejected("failed")));
+    let dead: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    assert_eq!(dead.finally(cleanup()).wait(), Err(PromiseError::Panicked(
+        Some("worker died".to_string()))));
+
+    assert_eq!(cleaned.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+pub fn test_all_report() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::new(|| {
+            thread::sleep(Duration::from_millis(100));
+            Ok(1)
+        }),
+        Promise::reject("failed").into_promise(),
+        Promise::new(|| panic!("worker died")),
+    ];
+    let AllReport { outcomes, resolved, rejected, dead, elapsed } =
+        Promise::all_report(promises).wait().unwrap();
+
+    assert_eq!((resolved, rejected, dead), (1, 1, 1));
+    match outcomes[0] {
+        Outcome::Ok(1, took) => assert!(took >= Duration::from_millis(100)),
+        ref other => panic!("unexpected first outcome {:?}", other)
+    }
+    match outcomes[1] {
+        Outcome::Err("failed", took) => assert!(took <= elapsed),
+        ref other => panic!("unexpected second outcome {:?}", other)
+    }
+    assert_eq!(outcomes[2], Outcome::Dead);
+    assert!(elapsed >= Duration::from_millis(100));
+}
+
+#[test]
+pub fn test_future_poll() {
+    let promise: Promise<u32, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(100));
+        Ok(7)
+    });
+    let (result, wakes) = block_on(promise.then_ok(|val| Ok(val * 6)));
+    assert_eq!(result, Ok(42));
+    assert_eq!(wakes, 1);
+
+    let (result, wakes) =
+        block_on(Promise::<u32, &str>::reject("failed").into_promise());
+    assert_eq!(result, Err("failed"));
+    assert_eq!(wakes, 0);
+}
+
+#[test]
+#[should_panic(expected = "its function died")]
+pub fn test_future_poll_dead() {
+    let promise: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    let _ = block_on(promise);
+}
+
+#[test]
+pub fn test_future_poll_threads() {
+    let promise: Promise<u32, &str> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(50));
+        Ok(7)
+    });
+    // The worker wakes the task itself, without a relay thread
+    let before = threads_spawned();
+    assert_eq!(block_on(promise), (Ok(7), 1));
+    assert_eq!(threads_spawned(), before);
+
+    let (tx, rx) = mpsc::channel();
+    let outside: Promise<u32, &str> = Promise::from_receiver(rx);
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        tx.send(Ok(8)).unwrap();
+    });
+    let before = threads_spawned();
+    assert_eq!(block_on(outside), (Ok(8), 1));
+    assert_eq!(threads_spawned() - before, 1);
+}
+
+#[test]
+pub fn test_new_on_shares_workers() {
+    let runtime = Runtime::new(2);
+    let before = threads_spawned();
+    let promises: Vec<Promise<usize, ()>> = (0..100)
+        .map(|index| Promise::new_on(&runtime, move || Ok(index * 2)))
+        .collect();
+    assert_eq!(threads_spawned(), before);
+
+    let values = Promise::all(promises).wait().unwrap();
+    assert_eq!(values.iter().sum::<usize>(), 9900);
+
+    let pooled: Promise<&str, ()> = Promise::new_pooled(|| Ok("pooled"));
+    assert_eq!(pooled.wait(), Ok("pooled"));
+
+    let panicked: Promise<u32, ()> =
+        Promise::new_on(&runtime, || panic!("queued"));
+    assert_eq!(panicked.wait(),
+               Err(PromiseError::Panicked(Some("queued".to_string()))));
+}
+
+#[test]
+pub fn test_runtime_with_capacity() {
+    let runtime = Runtime::with_capacity(1, 1);
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let (ran_tx, ran_rx) = mpsc::channel();
+
+    // Holds the worker, and queues more work from inside the runtime, which
+    // mustn't wait on the full queue
+    let inner = runtime.clone();
+    let inner_ran = ran_tx.clone();
+    runtime.execute(Box::new(move || {
+        release_rx.recv().unwrap();
+        inner.execute(Box::new(move || inner_ran.send("inner").unwrap()));
+    }));
+    thread::sleep(Duration::from_millis(50));
+    let first_ran = ran_tx.clone();
+    runtime.execute(Box::new(move || first_ran.send("first").unwrap()));
+
+    // The queue is full, so this waits until the worker is released
+    let queued = Arc::new(AtomicBool::new(false));
+    let producer = {
+        let runtime = runtime.clone();
+        let queued = queued.clone();
+        thread::spawn(move || {
+            runtime.execute(Box::new(move || ran_tx.send("second").unwrap()));
+            queued.store(true, Ordering::SeqCst);
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!queued.load(Ordering::SeqCst));
+
+    release_tx.send(()).unwrap();
+    producer.join().unwrap();
+    let mut ran: Vec<_> = ran_rx.iter().take(3).collect();
+    ran.sort();
+    assert_eq!(ran, vec!["first", "inner", "second"]);
+}
+
+#[test]
+pub fn test_delay() {
+    let start = Instant::now();
+    let delayed: Promise<u32, ()> =
+        Promise::delay(Duration::from_millis(100), 7);
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert_eq!(delayed.wait(), Ok(7));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    let called = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+    let delayed: Promise<u32, &str> = Promise::delay_with(
+        Duration::from_millis(100), {
+            let called = called.clone();
+            move || {
+                called.store(true, Ordering::SeqCst);
+                Err("failed")
+            }
+        });
+    assert!(!called.load(Ordering::SeqCst));
+    assert_eq!(delayed.wait(), Err(PromiseError::Rejected("failed")));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+pub fn test_timeout() {
+    let quick: Promise<u32, &str> =
+        Promise::delay(Duration::from_millis(10), 1);
+    assert_eq!(quick.timeout(Duration::from_secs(5), "too slow").wait(),
+               Ok(1));
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let slow: Promise<u32, &str> = Promise::new({
+        let finished = finished.clone();
+        move || {
+            thread::sleep(Duration::from_millis(200));
+            finished.store(true, Ordering::SeqCst);
+            Ok(2)
+        }
+    });
+    let start = Instant::now();
+    assert_eq!(slow.timeout(Duration::from_millis(50), "too slow").wait(),
+               Err(PromiseError::Rejected("too slow")));
+    assert!(start.elapsed() < Duration::from_millis(200));
+
+    // The worker carries on without the timed out promise
+    thread::sleep(Duration::from_millis(300));
+    assert!(finished.load(Ordering::SeqCst));
+}
+
+#[test]
+pub fn test_timeout_cancels() {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let promise: Promise<u32, &str> = Promise::new_cancellable({
+        let cancelled = cancelled.clone();
+        move |flag| {
+            while !flag.is_cancelled() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            cancelled.store(true, Ordering::SeqCst);
+            Ok(1)
+        }
+    });
+    assert_eq!(promise.timeout(Duration::from_millis(20), "too slow").wait(),
+               Err(PromiseError::Rejected("too slow")));
+
+    let start = Instant::now();
+    while !cancelled.load(Ordering::SeqCst) {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+pub fn test_all_settled() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::delay(Duration::from_millis(100), 1),
+        Promise::reject("failed").into_promise(),
+        Promise::new(|| panic!("worker died")),
+        Promise::resolve(4).into_promise(),
+    ];
+    assert_eq!(Promise::all_settled(promises).wait(), Ok(vec![
+        Ok(1),
+        Err(PromiseError::Rejected("failed")),
+        Err(PromiseError::Dead),
+        Ok(4),
+    ]));
+}
+
+#[test]
+pub fn test_all_input_order() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::delay(Duration::from_millis(150), 1),
+        Promise::delay(Duration::from_millis(100), 2),
+        Promise::delay(Duration::from_millis(50), 3),
+        Promise::resolve(4).into_promise(),
+    ];
+    assert_eq!(Promise::all(promises).wait(), Ok(vec![1, 2, 3, 4]));
+}
+
+#[test]
+pub fn test_any() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::reject("first failed").into_promise(),
+        Promise::delay(Duration::from_millis(50), 2),
+        Promise::delay(Duration::from_millis(500), 3),
+    ];
+    assert_eq!(Promise::any(promises).wait(), Ok(2));
+
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::delay_with(Duration::from_millis(50), || Err("slow")),
+        Promise::new(|| panic!("worker died")),
+        Promise::reject("quick").into_promise(),
+    ];
+    assert_eq!(Promise::any(promises).wait(),
+               Err(PromiseError::Rejected(vec!["slow", "quick"])));
+
+    assert_eq!(Promise::<u32, &str>::any(Vec::new()).wait(),
+               Err(PromiseError::Rejected(Vec::new())));
+}
+
+#[test]
+pub fn test_wait_panicked_chain() {
+    let upstream: Promise<u32, &str> =
+        Promise::new(|| panic!("worker {} died", 3));
+    let chained = upstream.map(|val| val + 1).then_err(|err| Err(err.len()));
+    assert_eq!(chained.wait(),
+               Err(PromiseError::Panicked(Some("worker 3 died".to_string()))));
+
+    let callback: Promise<u32, &str> = Promise::resolve(1).into_promise()
+        .then_ok(|_| panic!("callback died"));
+    assert_eq!(callback.wait(),
+               Err(PromiseError::Panicked(Some("callback died".to_string()))));
+
+    let (promise, work) = Promise::<u32, &str>::deferred_work(|| Ok(1));
+    drop(work);
+    assert_eq!(promise.wait(), Err(PromiseError::Dead));
+}
+
+#[test]
+pub fn test_from_receiver() {
+    let (tx, rx) = mpsc::channel();
+    let promise: Promise<u32, &str> = Promise::from_receiver(rx);
+    tx.send(Ok(41)).unwrap();
+    assert_eq!(promise.map(|val| val + 1).wait(), Ok(42));
+
+    let rx =
+        Promise::<u32, &str>::reject("failed").into_promise().into_receiver();
+    assert_eq!(rx.recv(), Ok(Err("failed")));
+}
+
+#[test]
+pub fn test_join() {
+    let name: Promise<String, &str> =
+        Promise::delay(Duration::from_millis(50), "answer".to_string());
+    let value: Promise<u32, &str> = Promise::resolve(42).into_promise();
+    assert_eq!(Promise::join2(name, value).wait(),
+               Ok(("answer".to_string(), 42)));
+
+    let flag: Promise<bool, &str> = Promise::resolve(true).into_promise();
+    let value: Promise<u32, &str> = Promise::resolve(42).into_promise();
+    let failed: Promise<char, &str> = Promise::reject("failed").into_promise();
+    assert_eq!(Promise::join3(flag, value, failed).wait(),
+               Err(PromiseError::Rejected("failed")));
+}
+
+#[test]
+pub fn test_retry() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let flaky: Promise<&str, usize> = Promise::retry(3, {
+        let calls = calls.clone();
+        move || match calls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err(1),
+            _ => Ok("connected")
+        }
+    });
+    assert_eq!(flaky.wait(), Ok("connected"));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let failing: Promise<&str, usize> = Promise::retry(2, {
+        let calls = calls.clone();
+        move || Err(calls.fetch_add(1, Ordering::SeqCst))
+    });
+    assert_eq!(failing.wait(), Err(PromiseError::Rejected(1)));
+}
+
+#[test]
+pub fn test_state() {
+    let (start_tx, start_rx) = mpsc::channel::<()>();
+    let promise: Promise<u32, &str> = Promise::new(move || {
+        start_rx.recv().unwrap();
+        Err("failed")
+    });
+    assert_eq!(promise.state(), PromiseState::Pending);
+    assert!(!promise.is_resolved());
+
+    start_tx.send(()).unwrap();
+    Promise::ready_on(slice::from_ref(&promise));
+    assert_eq!(promise.state(), PromiseState::Resolved);
+    assert!(promise.is_resolved());
+    assert_eq!(promise.wait(), Err(PromiseError::Rejected("failed")));
+
+    let dead: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    Promise::ready_on(slice::from_ref(&dead));
+    assert_eq!(dead.state(), PromiseState::Dead);
+}
+
+#[derive(Debug, PartialEq)]
+enum JobError {
+    Cancelled,
+    Failed
+}
+
+impl From<Cancelled> for JobError {
+    fn from(_: Cancelled) -> JobError {
+        JobError::Cancelled
+    }
+}
+
+#[test]
+pub fn test_new_with_token() {
+    let (promise, token) = Promise::new_with_token(|flag| {
+        while !flag.is_cancelled() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        Err::<u32, _>(JobError::Failed)
+    });
+    assert!(!token.is_cancelled());
+    token.clone().cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(promise.wait(),
+               Err(PromiseError::Rejected(JobError::Cancelled)));
+
+    let (promise, token) = Promise::<u32, JobError>::new_with_token(|_| Ok(1));
+    assert_eq!(promise.wait(), Ok(1));
+    assert!(!token.is_cancelled());
+
+    let cancellable: Promise<u32, JobError> = Promise::new_cancellable(|flag| {
+        while !flag.is_cancelled() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        Ok(2)
+    });
+    cancellable.cancel_token().unwrap().cancel();
+    assert_eq!(cancellable.wait(), Ok(2));
+    let settled: Promise<u32, ()> = Promise::resolve(1).into_promise();
+    assert!(settled.cancel_token().is_none());
+}
+
+#[test]
+pub fn test_all_limited() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let funcs: Vec<_> = (0..6).map(|index| {
+        let running = running.clone();
+        let peak = peak.clone();
+        move || {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // Later functions finish first, to check the order of the values
+            thread::sleep(Duration::from_millis(60 - index * 10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok::<u64, &str>(index)
+        }
+    }).collect();
+    assert_eq!(Promise::all_limited(funcs, 2).wait(),
+               Ok(vec![0, 1, 2, 3, 4, 5]));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+    let ran = Arc::new(AtomicUsize::new(0));
+    let funcs: Vec<_> = (0..4).map(|index| {
+        let ran = ran.clone();
+        move || {
+            ran.fetch_add(1, Ordering::SeqCst);
+            if index == 0 { Err("failed") } else { Ok(index) }
+        }
+    }).collect();
+    assert_eq!(Promise::all_limited(funcs, 1).wait(),
+               Err(PromiseError::Rejected("failed")));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(ran.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn test_all_limited_skipped_rejects() {
+    // Skipped functions must not make the batch look dead before the error
+    // which skipped them arrives
+    for _ in 0..1000 {
+        let funcs: Vec<_> = (0..51).map(|index| move || {
+            if index == 0 { Err("failed") } else { Ok(index) }
+        }).collect();
+        assert_eq!(Promise::all_limited(funcs, 8).wait(),
+                   Err(PromiseError::Rejected("failed")));
+    }
+
+    let funcs: Vec<fn() -> Result<u32, &'static str>> =
+        vec![|| Ok(1), || panic!("limited")];
+    assert_eq!(Promise::all_limited(funcs, 1).wait(),
+               Err(PromiseError::Panicked(Some("limited".to_string()))));
+}
+
+#[test]
+pub fn test_collect() {
+    let all: Promise<Vec<u32>, &str> = (1..4)
+        .map(|val| Promise::delay(Duration::from_millis(10), val * 2))
+        .collect();
+    assert_eq!(all.wait(), Ok(vec![2, 4, 6]));
+
+    let failed: Promise<Vec<u32>, &str> =
+        vec![Promise::resolve(1).into_promise(),
+             Promise::reject("failed").into_promise()]
+            .into_iter().collect();
+    assert_eq!(failed.wait(), Err(PromiseError::Rejected("failed")));
+}
+
+#[test]
+pub fn test_then_map() {
+    let status = |promise: Promise<u32, &'static str>| {
+        promise.then_map(|val| format!("got {}", val),
+                         |err| format!("failed: {}", err))
+    };
+    assert_eq!(status(Promise::resolve(3).into_promise()).wait(),
+               Ok("got 3".to_string()));
+    assert_eq!(status(Promise::reject("timeout").into_promise()).wait(),
+               Ok("failed: timeout".to_string()));
+}
+
+#[test]
+pub fn test_inspect() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let logged = |promise: Promise<u32, &'static str>| {
+        let ok_log = log.clone();
+        let err_log = log.clone();
+        promise
+            .inspect(move |val| ok_log.lock().unwrap().push(val.to_string()))
+            .inspect_err(move |err| {
+                err_log.lock().unwrap().push(err.to_string())
+            })
+    };
+    assert_eq!(logged(Promise::resolve(3).into_promise()).wait(), Ok(3));
+    assert_eq!(logged(Promise::reject("failed").into_promise()).wait(),
+               Err(PromiseError::Rejected("failed")));
+    assert_eq!(*log.lock().unwrap(), vec!["3", "failed"]);
+}
+
+#[test]
+pub fn test_race_ok() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::reject("quick failure").into_promise(),
+        Promise::delay(Duration::from_millis(50), 2),
+        Promise::new(|| panic!("worker died")),
+    ];
+    assert_eq!(Promise::race_ok(promises).wait(), Ok(2));
+
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::delay_with(Duration::from_millis(50), || Err("slow")),
+        Promise::reject("quick").into_promise(),
+    ];
+    assert_eq!(Promise::race_ok(promises).wait(),
+               Err(PromiseError::Rejected("slow")));
+}
+
+#[test]
+pub fn test_lazy() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let lazy: Promise<u32, ()> = Promise::lazy({
+        let ran = ran.clone();
+        move || {
+            ran.store(true, Ordering::SeqCst);
+            Ok(42)
+        }
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!ran.load(Ordering::SeqCst));
+    assert_eq!(lazy.state(), PromiseState::Pending);
+    assert_eq!(lazy.wait(), Ok(42));
+    assert!(ran.load(Ordering::SeqCst));
+
+    let ran = Arc::new(AtomicBool::new(false));
+    let unused: Promise<u32, ()> = Promise::lazy({
+        let ran = ran.clone();
+        move || {
+            ran.store(true, Ordering::SeqCst);
+            Ok(1)
+        }
+    });
+    drop(unused);
+    thread::sleep(Duration::from_millis(50));
+    assert!(!ran.load(Ordering::SeqCst));
+
+    let chained = Promise::<u32, ()>::lazy(|| Ok(20)).map(|val| val + 1);
+    assert_eq!(chained.wait(), Ok(21));
+}
+
+#[test]
+pub fn test_wait_timeout() {
+    let quick: Promise<u32, &str> = Promise::reject("failed").into_promise();
+    assert_eq!(quick.wait_timeout(Duration::from_secs(5)), Ok(Err("failed")));
+
+    let slow: Promise<u32, &str> = Promise::delay(Duration::from_secs(5), 1);
+    let start = Instant::now();
+    assert_eq!(slow.wait_timeout(Duration::from_millis(50)),
+               Err(TimeoutError::TimedOut));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let dead: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    assert_eq!(dead.wait_timeout(Duration::from_secs(5)),
+               Err(TimeoutError::Panicked(Some("worker died".to_string()))));
+}
+
+#[test]
+pub fn test_then_named() {
+    let settled: Promise<u32, ()> = Promise::resolve(1).into_promise();
+    let mut named: Promise<String, ()> = settled.then_named(
+        "parse-step",
+        |_: u32| Ok(thread::current().name().unwrap().to_string()),
+        |_: ()| Err(()));
+    let handle = named.take_handle().unwrap();
+    assert_eq!(handle.thread().name(), Some("parse-step"));
+    assert!(named.take_handle().is_none());
+    assert_eq!(named.wait(), Ok("parse-step".to_string()));
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+pub fn test_shared() {
+    let shared: SharedPromise<String, &str> =
+        Promise::delay(Duration::from_millis(50), "config".to_string())
+            .shared();
+    let lengths = shared.subscribe().map(|text| text.len());
+    let upper = shared.clone().subscribe().map(|text| text.to_uppercase());
+    assert_eq!(lengths.wait(), Ok(6));
+    assert_eq!(upper.wait(), Ok("CONFIG".to_string()));
+    // Subscribing late gets the kept result
+    assert_eq!(shared.subscribe().wait(), Ok("config".to_string()));
+
+    let dead: SharedPromise<u32, &str> =
+        Promise::new(|| panic!("worker died")).shared();
+    let early = dead.subscribe();
+    let died = PromiseError::Panicked(Some("worker died".to_string()));
+    assert_eq!(early.wait(), Err(died.clone()));
+    assert_eq!(dead.subscribe().wait(), Err(died));
+}
+
+#[test]
+pub fn test_chains_keep_panics() {
+    fn dead() -> Promise<u32, &'static str> {
+        Promise::new(|| panic!("worker died"))
+    }
+    fn died<E>() -> PromiseError<E> {
+        PromiseError::Panicked(Some("worker died".to_string()))
+    }
+
+    let never: Promise<(), ()> = Promise::new_blocking(|| {
+        thread::sleep(Duration::from_secs(5));
+        Ok(())
+    });
+    assert_eq!(dead().until_signal(never).wait(), Err(died()));
+    let again = |val| Promise::resolve(val).into_promise();
+    assert_eq!(dead().and_then(again).wait(), Err(died()));
+    let recover = |err| Promise::reject(err).into_promise();
+    assert_eq!(dead().recover_pipeline(recover).wait(), Err(died()));
+    assert_eq!(dead().timeout(Duration::from_secs(5), "late").wait(),
+               Err(died()));
+    assert_eq!(dead().with_finalizer(Ok, || ()).wait(), Err(died()));
+
+    // As does a chain onto a promise started by a callback, which dies
+    let resolved: Promise<u32, &str> = Promise::resolve(1).into_promise();
+    assert_eq!(resolved.and_then(|_| dead()).wait(), Err(died()));
+    let rejected: Promise<u32, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(rejected.recover_pipeline(|_| dead()).wait(), Err(died()));
+}
+
+#[test]
+pub fn test_flatten() {
+    let nested: Promise<Promise<u32, &str>, &str> =
+        Promise::new(|| Ok(Promise::delay(Duration::from_millis(20), 5)));
+    assert_eq!(nested.flatten().wait(), Ok(5));
+
+    let inner_failed: Promise<Promise<u32, &str>, &str> =
+        Promise::resolve(Promise::reject("inner").into_promise())
+            .into_promise();
+    assert_eq!(inner_failed.flatten().wait(),
+               Err(PromiseError::Rejected("inner")));
+
+    let outer_failed: Promise<Promise<u32, &str>, &str> =
+        Promise::reject("outer").into_promise();
+    assert_eq!(outer_failed.flatten().wait(),
+               Err(PromiseError::Rejected("outer")));
+}
+
+#[test]
+pub fn test_tally() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::delay(Duration::from_millis(50), 1),
+        Promise::resolve(2).into_promise(),
+        Promise::reject("failed").into_promise(),
+        Promise::new(|| panic!("worker died")),
+    ];
+    assert_eq!(Promise::tally(promises).wait(),
+               Ok(Tally { resolved: 2, rejected: 1, dead: 1 }));
+}
+
+#[test]
+pub fn test_stream() {
+    let promises: Vec<Promise<u32, &str>> = vec![
+        Promise::delay(Duration::from_millis(200), 1),
+        Promise::new(|| panic!("worker died")),
+        Promise::delay_with(Duration::from_millis(100), || Err("failed")),
+        Promise::resolve(3).into_promise(),
+    ];
+    let results: Vec<_> = Promise::stream(promises).collect();
+    assert_eq!(results, vec![Ok(3), Err("failed"), Ok(1)]);
+}
+
+#[test]
+pub fn test_unwrap_or() {
+    let resolved: Promise<u32, &str> = Promise::resolve(1).into_promise();
+    assert_eq!(resolved.unwrap_or(0).wait(), Ok(1));
+    let rejected: Promise<u32, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(rejected.unwrap_or(0).wait(), Ok(0));
+
+    let rejected: Promise<usize, &str> =
+        Promise::reject("failed").into_promise();
+    assert_eq!(rejected.unwrap_or_else(|err| err.len()).wait(), Ok(6));
+}
+
+#[test]
+pub fn test_generator() {
+    let before = threads_spawned();
+    let lines: PromiseStream<String, io::ErrorKind> =
+        PromiseStream::generator(|tx| {
+            for line in "first\nsecond\nthird".lines() {
+                thread::sleep(Duration::from_millis(10));
+                if tx.send(Ok(line.to_string())).is_err() {
+                    return;
+                }
+            }
+            tx.send(Err(io::ErrorKind::UnexpectedEof)).unwrap_or(());
+        });
+    let results: Vec<_> = lines.collect();
+    assert_eq!(results, vec![
+        Ok("first".to_string()),
+        Ok("second".to_string()),
+        Ok("third".to_string()),
+        Err(io::ErrorKind::UnexpectedEof),
+    ]);
+    assert_eq!(threads_spawned() - before, 1);
+}
+
+#[test]
+pub fn test_then_on() {
+    let pool = LocalPool::new();
+    let pool_thread = pool.spawn(|| -> Result<_, ()> {
+        Ok(thread::current().id())
+    }).wait().unwrap();
+
+    let promise: Promise<u32, String> = Promise::new(|| {
+        thread::sleep(Duration::from_millis(20));
+        Ok(20)
+    });
+    // Nothing waits for the promise on a thread, and the callback goes to
+    // the pool's worker
+    let before = threads_spawned();
+    let chained = promise.then_on(&pool, |val| {
+        Ok((val + 1, thread::current().id()))
+    }, |err: String| Err(err.len()));
+    assert_eq!(threads_spawned() - before, 0);
+    assert_eq!(chained.wait(), Ok((21, pool_thread)));
+
+    let failed: Promise<u32, String> =
+        Promise::reject("fail".to_string()).into_promise();
+    let runtime = Runtime::global();
+    let chained = failed.then_on(&runtime, |val| Ok(val + 1),
+                                 |err| Err(err.len()));
+    assert_eq!(chained.wait(), Err(PromiseError::Rejected(4)));
+}
+
+#[test]
+pub fn test_executor_runs_tasks() {
+    let (tx, rx) = mpsc::channel();
+    Executor::spawn(&Runtime::new(1), Box::new(move || {
+        tx.send(3).unwrap();
+    }));
+    assert_eq!(rx.recv(), Ok(3));
+}
+
+#[test]
+pub fn test_debug_shows_state() {
+    let (tx, rx) = mpsc::channel();
+    let promise: Promise<u32, String> = Promise::from_receiver(rx);
+    assert_eq!(format!("{:?}", promise), "Pending");
+    tx.send(Ok(5)).unwrap();
+    assert_eq!(format!("{:?}", promise), "Resolved(Ok(5))");
+    // Printing it didn't take the value
+    assert_eq!(format!("{:?}", promise), "Resolved(Ok(5))");
+    assert_eq!(promise.wait(), Ok(5));
+
+    let rejected: Promise<u32, String> =
+        Promise::reject("no".to_string()).into_promise();
+    assert_eq!(format!("{:?}", rejected), "Resolved(Err(\"no\"))");
+
+    let (tx, rx) = mpsc::channel::<Result<u32, String>>();
+    drop(tx);
+    assert_eq!(format!("{:?}", Promise::from_receiver(rx)), "Dead");
+}
+
+#[test]
+pub fn test_race_timeout() {
+    let slow: Promise<u32, &str> = Promise::new_blocking(|| {
+        thread::sleep(Duration::from_millis(500));
+        Ok(1)
+    });
+    let fast: Promise<u32, &str> = Promise::new_blocking(|| {
+        thread::sleep(Duration::from_millis(10));
+        Ok(2)
+    });
+    let raced = Promise::race_timeout(vec![slow, fast],
+                                      Duration::from_millis(200), "timeout");
+    assert_eq!(raced.wait(), Ok(2));
+
+    let slow: Promise<u32, &str> = Promise::new_blocking(|| {
+        thread::sleep(Duration::from_millis(500));
+        Ok(1)
+    });
+    let raced = Promise::race_timeout(vec![slow], Duration::from_millis(20),
+                                      "timeout");
+    assert_eq!(raced.wait(), Err(PromiseError::Rejected("timeout")));
+}
+
+#[test]
+pub fn test_race_timeout_all_dead() {
+    let dead: Promise<u32, &str> = Promise::new(|| panic!("worker died"));
+    let start = Instant::now();
+    let raced = Promise::race_timeout(vec![dead], Duration::from_secs(10),
+                                      "timeout");
+    assert_eq!(raced.wait(), Err(PromiseError::Rejected("timeout")));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+pub fn test_channel() {
+    let (resolver, promise): (Resolver<u32, String>, _) = Promise::channel();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        resolver.resolve(4);
+    });
+    assert_eq!(promise.wait(), Ok(4));
+
+    let (resolver, promise) = Promise::<u32, String>::channel();
+    resolver.reject("no".to_string());
+    assert_eq!(promise.wait(), Err(PromiseError::Rejected("no".to_string())));
+
+    let (resolver, promise) = Promise::<u32, String>::channel();
+    drop(resolver);
+    assert_eq!(promise.wait(), Err(PromiseError::Dead));
+}
+
+#[test]
+pub fn test_all_dies_with_input() {
+    let promises: Vec<Promise<u32, ()>> = vec![
+        Promise::resolve(1).into_promise(),
+        Promise::new(|| panic!("worker died")),
+        Promise::new(|| Ok(3)),
+    ];
+    assert_eq!(Promise::all(promises).wait(),
+               Err(PromiseError::Panicked(Some("worker died".to_string()))));
+
+    // Already dead inputs aren't dropped from the result either, though a
+    // bare channel has no panic to pass on
+    let (tx, rx) = mpsc::channel::<Result<u32, ()>>();
+    drop(tx);
+    let promises =
+        vec![Promise::resolve(1).into_promise(), Promise::from_receiver(rx)];
+    assert_eq!(Promise::all(promises).wait(), Err(PromiseError::Dead));
+
+    let dead: Vec<Promise<u32, ()>> = vec![
+        Promise::new(|| panic!("worker died")),
+    ];
+    assert_eq!(Promise::all(dead).wait(),
+               Err(PromiseError::Panicked(Some("worker died".to_string()))));
+}
+
+#[test]
+pub fn test_all_family_dies_with_input() {
+    fn died<E>() -> PromiseError<E> {
+        PromiseError::Panicked(Some("worker died".to_string()))
+    }
+    fn batch() -> Vec<Promise<u32, &'static str>> {
+        vec![Promise::resolve(1).into_promise(),
+             Promise::new(|| panic!("worker died"))]
+    }
+
+    assert_eq!(Promise::latency_all(batch()).wait().unwrap_err(), died());
+    assert_eq!(Promise::all_dedup(batch()).wait().unwrap_err(), died());
+    assert_eq!(Promise::all_partial(batch()).wait().unwrap_err(), died());
+    assert_eq!(Promise::reduce_parallel(batch(), |a, b| a + b).wait(),
+               Err(died()));
+    let combined = Promise::reduce_parallel(
+        vec![Promise::resolve(1).into_promise(),
+             Promise::resolve(2).into_promise()],
+        |_, _| -> u32 { panic!("worker died") });
+    assert_eq!(combined.wait(), Err(died::<&str>()));
+
+    let spilled: Vec<Promise<Record, ConfigError>> =
+        vec![Promise::new(|| panic!("worker died"))];
+    assert_eq!(Promise::all_spilled(spilled, 20).wait().unwrap_err(),
+               died());
+
+    let collector: AllCollector<u32, &str> = AllCollector::new();
+    for promise in batch() {
+        collector.submit(promise);
+    }
+    let result = collector.result();
+    collector.close();
+    assert_eq!(result.wait(), Err(died()));
+
+    let mut pair = batch();
+    let second = pair.pop().unwrap();
+    assert_eq!(all!(pair.pop().unwrap(), second).wait(), Err(died()));
+
+    let fanned: Promise<u32, &str> = Promise::resolve(2).into_promise();
+    assert_eq!(fanned.fan_out(|_| batch()).wait(), Err(died()));
+
+    let makers: Vec<fn() -> Result<u32, &'static str>> =
+        vec![|| Ok(1), || panic!("worker died")];
+    assert_eq!(Promise::all_from_fns_named("dies", makers).wait(),
+               Err(died()));
+}
+
+#[derive(Debug, PartialEq)]
+enum PollError {
+    Broken,
+    GaveUp
+}
+
+impl From<DeadlineExceeded> for PollError {
+    fn from(_: DeadlineExceeded) -> PollError {
+        PollError::GaveUp
+    }
+}
+
+#[test]
+pub fn test_poll_until() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let ready: Promise<&str, PollError> =
+        Promise::poll_until(Duration::from_millis(5), 10, move || {
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                3 => Ok(Some("ready")),
+                _ => Ok(None)
+            }
+        });
+    assert_eq!(settle(ready), Ok("ready"));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    let never: Promise<u32, PollError> =
+        Promise::poll_until(Duration::from_millis(1), 3, || Ok(None));
+    assert_eq!(settle(never), Err(PollError::GaveUp));
+
+    let broken: Promise<u32, PollError> =
+        Promise::poll_until(Duration::from_millis(1), 3,
+                            || Err(PollError::Broken));
+    assert_eq!(settle(broken), Err(PollError::Broken));
+}
+
+#[test]
+pub fn test_new_blocking_own_thread() {
+    let before = threads_spawned();
+    let blocking: Promise<thread::ThreadId, ()> =
+        Promise::new_blocking(|| Ok(thread::current().id()));
+    assert_eq!(threads_spawned() - before, 1);
+    assert_ne!(blocking.wait(), Ok(thread::current().id()));
+}
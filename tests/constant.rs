@@ -0,0 +1,64 @@
+// Counts heap allocations with a global allocator, which would count every
+// other test's allocations too, so this lives in its own test binary.
+extern crate promises;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use promises::{Promise, ReadyPromise};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const SEED: ReadyPromise<u32, ()> = Promise::constant(7);
+
+#[test]
+pub fn test_constant_allocates_nothing() {
+    let before = ALLOCATIONS.with(Cell::get);
+    let mut total = 0;
+    for val in 0..1000 {
+        let ready: ReadyPromise<u64, ()> = Promise::constant(val);
+        total += ready.map(|val| val * 2).into_result().unwrap();
+    }
+    let seeded = SEED.map(|val| val + 1).into_result();
+    let after = ALLOCATIONS.with(Cell::get);
+
+    assert_eq!(total, 999_000);
+    assert_eq!(seeded, Ok(8));
+    assert_eq!(after - before, 0);
+}
+
+#[test]
+pub fn test_resolved_promise_allocates_nothing() {
+    let before = ALLOCATIONS.with(Cell::get);
+    let mut total = 0;
+    for val in 0..1000 {
+        let resolved: Promise<u64, ()> = Promise::resolve(val).into_promise();
+        assert_eq!(resolved.try_wait(), Some(Ok(val)));
+        total += resolved.wait().unwrap();
+    }
+    let rejected: Promise<u64, ()> = Promise::reject(()).into_promise();
+    let rejected = rejected.wait();
+    let after = ALLOCATIONS.with(Cell::get);
+
+    assert_eq!(total, 499_500);
+    assert!(rejected.is_err());
+    assert_eq!(after - before, 0);
+}
@@ -0,0 +1,64 @@
+// The concurrency cap is process-wide, so this lives in its own test binary
+// where it can't throttle unrelated tests.
+extern crate promises;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use promises::{Promise, Runtime};
+
+// Runs six functions through `make` and returns how many ran at once.
+fn peak_running<F>(make: F) -> usize
+where F: Fn(Box<dyn FnOnce() -> Result<(), ()> + Send>) -> Promise<(), ()> {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut promises: Vec<Promise<(), ()>> = Vec::new();
+    for _ in 0..6 {
+        let running = running.clone();
+        let peak = peak.clone();
+        promises.push(make(Box::new(move || {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        })));
+    }
+
+    let (tx, rx) = mpsc::channel();
+    Promise::all(promises).then_result(move |result| {
+        tx.send(result.map(|values| values.len())).unwrap();
+        Ok::<(), ()>(())
+    });
+    assert_eq!(rx.recv().unwrap(), Ok(6));
+    peak.load(Ordering::SeqCst)
+}
+
+#[test]
+pub fn test_set_global_concurrency() {
+    Promise::set_global_concurrency(2);
+
+    assert_eq!(peak_running(Promise::new_blocking), 2);
+    let runtime = Runtime::new(4);
+    assert_eq!(peak_running(|func| Promise::new_on(&runtime, func)), 2);
+
+    // A parked cooperative task gives its slot to the one it makes way for
+    Promise::set_global_concurrency(1);
+    Runtime::new(1).install();
+    let (queued_tx, queued_rx) = mpsc::channel();
+    let first: Promise<&str, ()> = Promise::new_cooperative(move |co| {
+        queued_rx.recv().unwrap();
+        co.yield_point();
+        Ok("first")
+    });
+    let second: Promise<&str, ()> = Promise::new_cooperative(|_| Ok("second"));
+    queued_tx.send(()).unwrap();
+    assert_eq!(Promise::all(vec![first, second]).wait(),
+               Ok(vec!["first", "second"]));
+    Runtime::uninstall();
+
+    Promise::set_global_concurrency(0);
+}
@@ -0,0 +1,48 @@
+// Measures the CPU time of the whole process, so this lives in its own test
+// binary where other tests can't add to it. The times come from `/proc`.
+#![cfg(target_os = "linux")]
+
+extern crate promises;
+
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use promises::Promise;
+
+/// The user and system CPU time used by the process so far, in clock ticks.
+fn cpu_ticks() -> u64 {
+    let stat = fs::read_to_string("/proc/self/stat").unwrap();
+    // The command name may contain spaces, but is closed by the last `)`
+    let fields: Vec<&str> =
+        stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let utime: u64 = fields[11].parse().unwrap();
+    let stime: u64 = fields[12].parse().unwrap();
+    utime + stime
+}
+
+#[test]
+pub fn test_waiting_combinators_do_not_spin() {
+    let (race_tx, race_rx) = mpsc::channel::<()>();
+    let (all_tx, all_rx) = mpsc::channel::<()>();
+    let raced: Promise<u32, ()> = Promise::race(vec![
+        Promise::new(move || { race_rx.recv().unwrap(); Ok(1) }),
+        Promise::new(|| panic!("worker died")),
+    ]);
+    let all: Promise<Vec<u32>, ()> = Promise::all(vec![
+        Promise::resolve(1).into_promise(),
+        Promise::new(move || { all_rx.recv().unwrap(); Ok(2) }),
+    ]);
+
+    let before = cpu_ticks();
+    thread::sleep(Duration::from_millis(500));
+    let spent = cpu_ticks() - before;
+    // A thread spinning for the whole wait would use around 50 ticks
+    assert!(spent < 10, "used {} ticks of CPU while waiting", spent);
+
+    race_tx.send(()).unwrap();
+    all_tx.send(()).unwrap();
+    assert_eq!(raced.wait(), Ok(1));
+    assert_eq!(all.wait(), Ok(vec![1, 2]));
+}
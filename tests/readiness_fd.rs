@@ -0,0 +1,60 @@
+// Waits on the readiness descriptor with the C library's `poll`, so it only
+// builds on Unix with the `readiness-fd` feature enabled.
+#![cfg(all(unix, feature = "readiness-fd"))]
+
+extern crate promises;
+
+use std::os::raw::{c_int, c_short, c_ulong};
+use std::sync::mpsc;
+
+use promises::Promise;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short
+}
+
+const POLLIN: c_short = 0x1;
+
+extern "C" {
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+/// Polls the descriptor for reading, returning whether it became readable
+/// within the timeout.
+fn poll_readable(fd: c_int, timeout_ms: c_int) -> bool {
+    let mut pollfd = PollFd { fd, events: POLLIN, revents: 0 };
+    let ready = unsafe { poll(&mut pollfd, 1, timeout_ms) };
+    assert!(ready >= 0, "poll failed");
+    ready == 1 && pollfd.revents & POLLIN != 0
+}
+
+fn settle<T, E>(promise: Promise<T, E>) -> Result<T, E>
+where T: Send + 'static, E: Send + 'static {
+    let (tx, rx) = mpsc::channel();
+    promise.then_result(move |result| {
+        tx.send(result).unwrap();
+        Ok::<(), ()>(())
+    });
+    rx.recv().unwrap()
+}
+
+#[test]
+pub fn test_readiness_fd_polls_readable() {
+    let (start_tx, start_rx) = mpsc::channel::<()>();
+    let promise: Promise<u32, ()> = Promise::new(move || {
+        start_rx.recv().unwrap();
+        Ok(42)
+    });
+    let fd = promise.readiness_fd();
+
+    assert!(!poll_readable(fd, 50));
+    assert_eq!(promise.readiness_fd(), fd);
+
+    start_tx.send(()).unwrap();
+
+    assert!(poll_readable(fd, 5000));
+    assert_eq!(settle(promise), Ok(42));
+}
@@ -0,0 +1,174 @@
+// Installing a runtime affects the whole process, so these tests get their
+// own binary and take turns with the global runtime.
+extern crate promises;
+
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use promises::{Promise, Runtime};
+
+static GLOBAL_RUNTIME: Mutex<()> = Mutex::new(());
+
+// Blocks the test until the promise settles and hands back its result.
+fn settle<T, E>(promise: Promise<T, E>) -> Result<T, E>
+where T: Send + 'static, E: Send + 'static {
+    let (tx, rx) = mpsc::channel();
+    promise.then_result(move |result| {
+        tx.send(result).unwrap();
+        Ok::<(), ()>(())
+    });
+    rx.recv().unwrap()
+}
+
+#[test]
+pub fn test_new_cooperative_interleaves() {
+    let _global = GLOBAL_RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    Runtime::new(1).install();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let (queued_tx, queued_rx) = mpsc::channel();
+
+    let first_log = log.clone();
+    let first: Promise<(), ()> = Promise::new_cooperative(move |co| {
+        first_log.lock().unwrap().push("first start");
+        // Don't yield until the second task is waiting in the queue
+        queued_rx.recv().unwrap();
+        co.yield_point();
+        first_log.lock().unwrap().push("first end");
+        Ok(())
+    });
+    let second_log = log.clone();
+    let second: Promise<(), ()> = Promise::new_cooperative(move |co| {
+        second_log.lock().unwrap().push("second start");
+        co.yield_point();
+        second_log.lock().unwrap().push("second end");
+        Ok(())
+    });
+    queued_tx.send(()).unwrap();
+
+    assert_eq!(settle(first), Ok(()));
+    assert_eq!(settle(second), Ok(()));
+    assert_eq!(*log.lock().unwrap(),
+               vec!["first start", "second start", "first end", "second end"]);
+
+    Runtime::uninstall();
+}
+
+#[test]
+pub fn test_yield_point_to_pinned() {
+    let _global = GLOBAL_RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    let runtime = Runtime::new(1);
+    runtime.install();
+    let worker = runtime.workers()[0];
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let (started_tx, started_rx) = mpsc::channel();
+    let (queued_tx, queued_rx) = mpsc::channel();
+
+    let yielder_log = log.clone();
+    let yielder: Promise<(), ()> = Promise::new_cooperative(move |co| {
+        started_tx.send(()).unwrap();
+        // Don't yield until the pinned task is waiting for the worker
+        queued_rx.recv().unwrap();
+        co.yield_point();
+        yielder_log.lock().unwrap().push("yielder");
+        Ok(())
+    });
+    started_rx.recv().unwrap();
+    let pinned_log = log.clone();
+    let pinned: Promise<(), ()> = Promise::new_pinned(worker, move || {
+        pinned_log.lock().unwrap().push("pinned");
+        Ok(())
+    });
+    queued_tx.send(()).unwrap();
+
+    assert_eq!(settle(yielder), Ok(()));
+    assert_eq!(settle(pinned), Ok(()));
+    assert_eq!(*log.lock().unwrap(), vec!["pinned", "yielder"]);
+
+    Runtime::uninstall();
+}
+
+#[test]
+pub fn test_yield_point_waited_on() {
+    let _global = GLOBAL_RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    Runtime::new(2).install();
+
+    // Keeps the other worker busy until the waiter has been queued
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let blocker: Promise<(), ()> = Promise::new_cooperative(move |_| {
+        release_rx.recv().unwrap();
+        Ok(())
+    });
+    let (queued_tx, queued_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+    let yielder: Promise<(), ()> = Promise::new_cooperative(move |co| {
+        queued_rx.recv().unwrap();
+        co.yield_point();
+        done_tx.send(()).unwrap();
+        Ok(())
+    });
+    // Runs while the yielder is parked, and waits on it
+    let waiter: Promise<(), ()> = Promise::new_cooperative(move |_| {
+        done_rx.recv().unwrap();
+        Ok(())
+    });
+    queued_tx.send(()).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    release_tx.send(()).unwrap();
+
+    assert_eq!(settle(blocker), Ok(()));
+    assert_eq!(settle(yielder), Ok(()));
+    assert_eq!(settle(waiter), Ok(()));
+
+    Runtime::uninstall();
+}
+
+#[test]
+pub fn test_new_pinned_same_thread() {
+    let _global = GLOBAL_RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    let runtime = Runtime::new(3);
+    runtime.install();
+    let worker = runtime.workers()[1];
+
+    let promises: Vec<Promise<thread::ThreadId, ()>> = (0..3)
+        .map(|_| Promise::new_pinned(worker, || Ok(thread::current().id())))
+        .collect();
+    let ids: Vec<_> =
+        promises.into_iter().map(|p| settle(p).unwrap()).collect();
+
+    assert_eq!(runtime.workers().len(), 3);
+    assert!(ids.iter().all(|&id| id == ids[0]));
+    assert!(ids[0] != thread::current().id());
+
+    Runtime::uninstall();
+}
+
+#[test]
+pub fn test_last_timing_queue_wait() {
+    let _global = GLOBAL_RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    Runtime::new(1).install();
+
+    let first: Promise<(), ()> = Promise::new_cooperative(|_| {
+        thread::sleep(Duration::from_millis(200));
+        Ok(())
+    });
+    let second: Promise<(), ()> = Promise::new_cooperative(|_| Ok(()));
+    assert_eq!(first.last_timing(), None);
+
+    // The single worker runs the second after the first, so waiting for it
+    // (without consuming it) waits for both
+    Promise::ready_on(slice::from_ref(&second));
+    let first = first.last_timing().unwrap();
+    let second = second.last_timing().unwrap();
+
+    assert!(first.executed >= Duration::from_millis(200));
+    assert!(second.queued >= first.executed);
+    assert!(second.queued < first.executed + Duration::from_millis(100));
+    assert!(second.executed < Duration::from_millis(100));
+
+    Runtime::uninstall();
+}